@@ -0,0 +1,128 @@
+use std::{io, path::PathBuf, str::Utf8Error};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ModletError {
+    #[error("Invalid glob pattern: {0}")]
+    GlobPattern(#[from] glob::PatternError),
+    #[error(transparent)]
+    Glob(#[from] glob::GlobError),
+    #[error("Invalid XML at position {position}: {source}")]
+    InvalidDocument { position: usize, source: quick_xml::Error },
+    #[error("Invalid game directory {}: Data/Config does not exist", .0.display())]
+    InvalidGameDirectory(PathBuf),
+    #[error("{}: {source}", path.display())]
+    InvalidLocalization { path: PathBuf, source: csv::Error },
+    #[error("{}: {message}", path.display())]
+    InvalidPatchFile { path: PathBuf, message: String },
+    #[error("Invalid xpath `{xpath}`: {source}")]
+    InvalidXPath { xpath: String, source: XPathError },
+    #[error("Invalid XML: {0}")]
+    InvalidXml(quick_xml::Error),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error("The document has no root element")]
+    MissingRootElement,
+    #[error("<{command}> is missing its `{attribute}` attribute")]
+    MissingAttribute { command: String, attribute: &'static str },
+    #[error(transparent)]
+    Modinfo(#[from] modinfo::ModinfoError),
+    #[error("{}: file not found", .0.display())]
+    NotFound(PathBuf),
+    #[error("{}:{line}:{column}: {kind}", path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        kind: ParseErrorKind,
+    },
+    #[error("Root elements differ: <{vanilla}> and <{edited}>")]
+    RootElementMismatch { vanilla: String, edited: String },
+    #[error("Invalid UTF-8: {0}")]
+    Utf8(#[from] Utf8Error),
+    #[error("Failed to write XML: {0}")]
+    WriteError(#[from] quick_xml::Error),
+}
+
+impl ModletError {
+    /// The tag the error occurred in, if known
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            ModletError::Parse { kind, .. } => kind.tag(),
+            ModletError::MissingAttribute { command, .. } => Some(command),
+            _ => None,
+        }
+    }
+}
+
+/// An xpath which could not be parsed
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+#[error("{message} at position {position}")]
+pub struct XPathError {
+    pub message: String,
+    /// The (0-based, byte) position in the xpath at which the error occurred
+    pub position: usize,
+}
+
+impl XPathError {
+    pub fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+/// A `<conditional>` branch's `cond` attribute which could not be parsed
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+#[error("{message}")]
+pub struct ConditionError {
+    pub message: String,
+}
+
+impl ConditionError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+/// The reason a modlet XML file failed to parse
+#[derive(Debug, Error)]
+pub enum ParseErrorKind {
+    #[error("Invalid attribute in <{tag}>: {message}")]
+    InvalidAttribute { tag: String, message: String },
+    #[error("Invalid condition in <{tag}>: {message}")]
+    InvalidCondition { tag: String, message: String },
+    #[error("Invalid UTF-8 in <{tag}>")]
+    InvalidUtf8 { tag: String },
+    #[error("{0}")]
+    Reader(quick_xml::Error),
+    #[error("Unexpected end of file, expected </{tag}>")]
+    UnexpectedEof { tag: String },
+    #[error("Unhandled empty tag <{tag}/>")]
+    UnhandledEmptyTag { tag: String },
+    #[error("Unhandled event {event} in <{tag}>")]
+    UnhandledEvent { tag: String, event: String },
+    #[error("Unhandled tag <{tag}>")]
+    UnhandledStartTag { tag: String },
+    #[error("Unhandled text \"{text}\" in <{tag}>")]
+    UnhandledText { tag: String, text: String },
+}
+
+impl ParseErrorKind {
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            ParseErrorKind::InvalidAttribute { tag, .. }
+            | ParseErrorKind::InvalidCondition { tag, .. }
+            | ParseErrorKind::InvalidUtf8 { tag }
+            | ParseErrorKind::UnexpectedEof { tag }
+            | ParseErrorKind::UnhandledEmptyTag { tag }
+            | ParseErrorKind::UnhandledEvent { tag, .. }
+            | ParseErrorKind::UnhandledStartTag { tag }
+            | ParseErrorKind::UnhandledText { tag, .. } => Some(tag),
+            ParseErrorKind::Reader(_) => None,
+        }
+    }
+}
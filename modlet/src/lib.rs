@@ -2,4 +2,12 @@
 #[macro_use]
 extern crate rstest;
 
+mod error;
+pub mod lint;
 pub mod modlet;
+pub mod simulator;
+#[cfg(test)]
+mod test_helpers;
+pub mod xpath;
+
+pub use error::{ConditionError, ModletError, ParseErrorKind, XPathError};
@@ -0,0 +1,456 @@
+/// This module contains the modlet `Linter`, which checks modlet XML for common mistakes that the game either
+/// rejects or silently ignores. Each `Rule` has a default `Severity`, which can be changed (or turned off) per
+/// `Linter`, and any rule can be suppressed for a single file with a comment such as
+/// `<!-- 7dmt allow: unknown-tag, text-in-remove -->`.
+use crate::{
+    modlet::{Command, ModletXML, Span},
+    xpath::{self, Axis, BinaryOp, Expr, LocationPath, NodeTest},
+    ModletError,
+};
+use glob::glob;
+use quick_xml::events::Event;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The comment prefix which suppresses rules for the rest of a file
+const SUPPRESSION_PREFIX: &str = "7dmt allow:";
+
+#[derive(Debug, Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Rule {
+    /// An xpath which cannot be parsed (or is missing)
+    InvalidXPath,
+    /// A `<setattribute>` without the `name` of the attribute to set
+    MissingName,
+    /// A file which cannot be parsed at all
+    ParseError,
+    /// A predicate comparing to an empty string, or to an unquoted name (e.g. `[@name=gunPistol]`)
+    SuspiciousPredicate,
+    /// Text or markup inside a `<remove>` or `<removeattribute>`, which is ignored
+    TextInRemove,
+    /// A file patching a config which doesn't exist in the vanilla `Data/Config`
+    UnknownConfigFile,
+    /// A tag which isn't a modlet command, which the game ignores
+    UnknownTag,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::InvalidXPath,
+        Rule::MissingName,
+        Rule::ParseError,
+        Rule::SuspiciousPredicate,
+        Rule::TextInRemove,
+        Rule::UnknownConfigFile,
+        Rule::UnknownTag,
+    ];
+
+    /// The name used to configure or suppress the rule
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::InvalidXPath => "invalid-xpath",
+            Rule::MissingName => "missing-name",
+            Rule::ParseError => "parse-error",
+            Rule::SuspiciousPredicate => "suspicious-predicate",
+            Rule::TextInRemove => "text-in-remove",
+            Rule::UnknownConfigFile => "unknown-config-file",
+            Rule::UnknownTag => "unknown-tag",
+        }
+    }
+
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Rule::SuspiciousPredicate | Rule::TextInRemove => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.name() == name.trim())
+            .ok_or_else(|| format!("Unknown lint rule `{name}`"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// The rule is not checked
+    Allow,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Allow => write!(f, "allow"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by a lint rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub path: PathBuf,
+    pub rule: Rule,
+    pub severity: Severity,
+    /// The command the problem was found in, if it was loaded with a location
+    pub span: Option<Span>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{span}")?,
+            None => write!(f, "{}", self.path.display())?,
+        }
+        write!(f, ": {}[{}]: {}", self.severity, self.rule, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+    /// Severities which differ from each rule's default
+    severities: BTreeMap<Rule, Severity>,
+    /// The vanilla `Data/Config` directory, if known
+    vanilla_config: Option<PathBuf>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_severity(&mut self, rule: Rule, severity: Severity) {
+        self.severities.insert(rule, severity);
+    }
+
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities
+            .get(&rule)
+            .copied()
+            .unwrap_or_else(|| rule.default_severity())
+    }
+
+    /// Enables the `unknown-config-file` rule, checking file names against the vanilla `Data/Config` directory
+    pub fn set_vanilla_config(&mut self, config_dir: impl AsRef<Path>) {
+        self.vanilla_config = Some(config_dir.as_ref().to_path_buf());
+    }
+
+    /// Lints each XML file in a modlet's `Config` directory
+    ///
+    /// # Errors
+    ///
+    /// * If the Config directory cannot be read
+    pub fn lint(&self, modlet: impl AsRef<Path>) -> Result<Vec<Diagnostic>, ModletError> {
+        let mut diagnostics = Vec::new();
+        let pattern = modlet.as_ref().join("Config/**/*.xml");
+
+        for path in glob(&pattern.to_string_lossy())? {
+            let path = path?;
+            let source = String::from_utf8_lossy(&fs::read(&path)?).into_owned();
+            diagnostics.extend(self.lint_source(&path, &source));
+        }
+
+        Ok(diagnostics)
+    }
+
+    /// Lints a single modlet XML file
+    pub fn lint_source(&self, path: impl AsRef<Path>, source: &str) -> Vec<Diagnostic> {
+        let path = path.as_ref();
+        let mut found = Vec::new();
+
+        match ModletXML::new(path).read(source) {
+            Ok(xml) => {
+                self.check_file(&xml, &mut found);
+                check_commands(&xml.commands, &mut found);
+
+                let suppressed = suppressions(&xml);
+                found.retain(|(rule, ..)| !suppressed.contains(rule));
+            }
+            Err(err) => found.push((Rule::ParseError, None, err.to_string())),
+        }
+
+        found
+            .into_iter()
+            .filter_map(|(rule, span, message)| match self.severity(rule) {
+                Severity::Allow => None,
+                severity => Some(Diagnostic {
+                    message,
+                    path: path.to_path_buf(),
+                    rule,
+                    severity,
+                    span,
+                }),
+            })
+            .collect()
+    }
+
+    fn check_file(&self, xml: &ModletXML, found: &mut Vec<Found>) {
+        let Some(vanilla_config) = &self.vanilla_config else {
+            return;
+        };
+
+        let filename = xml.filename();
+        if !vanilla_config.join(&filename).exists() {
+            found.push((
+                Rule::UnknownConfigFile,
+                None,
+                format!(
+                    "{} is not a vanilla config file, so is never loaded",
+                    filename.display()
+                ),
+            ));
+        }
+    }
+}
+
+/// A problem found by a rule, before its severity is applied
+type Found = (Rule, Option<Span>, String);
+
+fn check_commands(commands: &[Command], found: &mut Vec<Found>) {
+    for command in commands {
+        match command {
+            Command::Conditional(branches) => {
+                branches
+                    .iter()
+                    .for_each(|branch| check_commands(&branch.commands, found));
+                continue;
+            }
+            Command::Unknown(tag, _, span) => {
+                found.push((
+                    Rule::UnknownTag,
+                    span.clone(),
+                    format!("<{tag}> is not a modlet command"),
+                ));
+                continue;
+            }
+            _ => (),
+        }
+
+        let Some(is) = command.instructions() else {
+            continue;
+        };
+        let span = is.span.clone();
+        let xpath = String::from_utf8_lossy(&is.xpath);
+
+        match xpath::parse_union(&xpath) {
+            _ if xpath.trim().is_empty() => {
+                found.push((Rule::InvalidXPath, span.clone(), format!("<{command}> has no xpath")));
+            }
+            Ok(paths) => {
+                for message in paths.iter().flat_map(suspicious_predicates) {
+                    found.push((Rule::SuspiciousPredicate, span.clone(), message));
+                }
+            }
+            Err(err) => {
+                found.push((
+                    Rule::InvalidXPath,
+                    span.clone(),
+                    format!("Invalid xpath `{xpath}`: {err}"),
+                ));
+            }
+        }
+
+        match command {
+            Command::SetAttribute(is) if is.attribute.is_none() => {
+                found.push((Rule::MissingName, span, format!("<{command}> has no `name` attribute")));
+            }
+            Command::Remove(is) | Command::RemoveAttribute(is) if has_content(&is.values) => {
+                found.push((
+                    Rule::TextInRemove,
+                    span,
+                    format!("The content of <{command}> is ignored"),
+                ));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn has_content(values: &[Event]) -> bool {
+    values.iter().any(|event| match event {
+        Event::Text(text) => !text.iter().all(u8::is_ascii_whitespace),
+        Event::Comment(_) => false,
+        _ => true,
+    })
+}
+
+/// Finds comparisons to an empty string, or to an unquoted name, within a path's predicates
+fn suspicious_predicates(path: &LocationPath) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut pending: Vec<&Expr> = path.steps.iter().flat_map(|step| &step.predicates).collect();
+
+    while let Some(expr) = pending.pop() {
+        match expr {
+            Expr::Binary(lhs, op, rhs) => {
+                if matches!(op, BinaryOp::Eq | BinaryOp::NotEq) {
+                    match (lhs.as_ref(), rhs.as_ref()) {
+                        (Expr::Path(path), Expr::Literal(value)) | (Expr::Literal(value), Expr::Path(path))
+                            if value.is_empty() =>
+                        {
+                            messages.push(format!("`{path}` is compared to an empty string"));
+                        }
+                        (Expr::Path(lhs), Expr::Path(rhs)) if is_attribute(lhs) && is_bare_name(rhs) => {
+                            messages.push(format!(
+                                "`{lhs}` is compared to the element `{rhs}`; did you mean '{rhs}'?"
+                            ));
+                        }
+                        _ => (),
+                    }
+                }
+                pending.push(lhs);
+                pending.push(rhs);
+            }
+            Expr::Function(_, args) => pending.extend(args),
+            Expr::Path(path) => pending.extend(path.steps.iter().flat_map(|step| &step.predicates)),
+            Expr::Literal(_) | Expr::Number(_) => (),
+        }
+    }
+
+    messages
+}
+
+/// Whether a path is a relative attribute, e.g. `@name`
+fn is_attribute(path: &LocationPath) -> bool {
+    !path.absolute && path.steps.len() == 1 && path.target_attribute().is_some()
+}
+
+/// Whether a path is a single relative element name, as an unquoted value parses
+fn is_bare_name(path: &LocationPath) -> bool {
+    matches!(
+        path.steps.as_slice(),
+        [step] if !path.absolute && step.axis == Axis::Child && step.predicates.is_empty() && matches!(step.test, NodeTest::Name(_))
+    )
+}
+
+/// The rules suppressed by `<!-- 7dmt allow: rule, ... -->` comments anywhere in the file
+fn suppressions(xml: &ModletXML) -> BTreeSet<Rule> {
+    let mut comments: Vec<String> = xml
+        .epilog
+        .iter()
+        .filter_map(|event| match event {
+            Event::Comment(comment) => Some(String::from_utf8_lossy(comment).into_owned()),
+            _ => None,
+        })
+        .collect();
+    let mut pending: Vec<&Command> = xml.commands.iter().collect();
+    while let Some(command) = pending.pop() {
+        match command {
+            Command::Comment(comment, _) => comments.push(comment.to_string()),
+            Command::Conditional(branches) => pending.extend(branches.iter().flat_map(|branch| &branch.commands)),
+            _ => (),
+        }
+    }
+
+    comments
+        .iter()
+        .filter_map(|comment| comment.trim().strip_prefix(SUPPRESSION_PREFIX))
+        .flat_map(|rules| rules.split(','))
+        .filter_map(|name| name.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::TempDir;
+
+    fn lint(body: &str) -> Vec<(Rule, Severity)> {
+        let source = format!("<config>\n{body}\n</config>");
+
+        Linter::new()
+            .lint_source("Config/items.xml", &source)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.severity))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::clean("<set xpath=\"/items/item[@name='a']/@b\">1</set>", vec![])]
+    #[case::unknown_tag("<sett xpath=\"/a\">1</sett>", vec![(Rule::UnknownTag, Severity::Error)])]
+    #[case::unknown_empty_tag("<sett/>", vec![(Rule::UnknownTag, Severity::Error)])]
+    #[case::invalid_xpath("<remove xpath=\"/items/item[]\"/>", vec![(Rule::InvalidXPath, Severity::Error)])]
+    #[case::missing_xpath("<remove/>", vec![(Rule::InvalidXPath, Severity::Error)])]
+    #[case::unquoted("<remove xpath=\"/items/item[@name=gunPistol]\"/>", vec![(Rule::SuspiciousPredicate, Severity::Warning)])]
+    #[case::union("<remove xpath=\"/items/item[@name='a'] | /blocks/block[@name='']\"/>", vec![(Rule::SuspiciousPredicate, Severity::Warning)])]
+    #[case::empty("<remove xpath=\"/items/item[@name='']\"/>", vec![(Rule::SuspiciousPredicate, Severity::Warning)])]
+    #[case::missing_name("<setattribute xpath=\"/a\">1</setattribute>", vec![(Rule::MissingName, Severity::Error)])]
+    #[case::text_in_remove("<remove xpath=\"/a\">b</remove>", vec![(Rule::TextInRemove, Severity::Warning)])]
+    #[case::nested(
+        "<conditional><if cond=\"mod_loaded('A')\"><sett></sett></if></conditional>",
+        vec![(Rule::UnknownTag, Severity::Error)]
+    )]
+    #[case::parse_error("<set xpath=\"/a\">", vec![(Rule::ParseError, Severity::Error)])]
+    #[case::unknown_empty_tag_and_text_in_remove(
+        "<sett/><remove xpath=\"/a\">b</remove>",
+        vec![(Rule::UnknownTag, Severity::Error), (Rule::TextInRemove, Severity::Warning)]
+    )]
+    #[case::suppressed_empty_tag("<!-- 7dmt allow: unknown-tag --><sett/>", vec![])]
+    #[case::suppressed("<!-- 7dmt allow: unknown-tag, text-in-remove --><sett></sett><remove xpath=\"/a\">b</remove>", vec![])]
+    fn test_lint(#[case] body: &str, #[case] expected: Vec<(Rule, Severity)>) {
+        assert_eq!(expected, lint(body));
+    }
+
+    #[rstest]
+    #[case::unknown_tag("<config>\n    <sett>1</sett>\n</config>", (2, 5, "<sett>1</sett>"))]
+    #[case::unknown_empty_tag("<config>\n\n  <sett/>\n</config>", (3, 3, "<sett/>"))]
+    #[case::comment_before("<config>\n<!-- a -->\n<sett/>\n</config>", (3, 1, "<sett/>"))]
+    fn test_unknown_tag_span(#[case] source: &str, #[case] expected: (usize, usize, &str)) {
+        let diagnostics = Linter::new().lint_source("Config/items.xml", source);
+        let span = diagnostics[0].span.as_ref().unwrap();
+
+        assert_eq!(expected, (span.line, span.column, &source[span.range.clone()]));
+    }
+
+    #[test]
+    fn test_severity() {
+        let mut linter = Linter::new();
+        linter.set_severity(Rule::UnknownTag, Severity::Allow);
+        linter.set_severity(Rule::TextInRemove, Severity::Error);
+
+        let diagnostics = linter.lint_source(
+            "Config/items.xml",
+            "<config><sett></sett><remove xpath=\"/a\">b</remove></config>",
+        );
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            (Rule::TextInRemove, Severity::Error),
+            (diagnostics[0].rule, diagnostics[0].severity)
+        );
+    }
+
+    #[test]
+    fn test_unknown_config_file() {
+        let vanilla = TempDir::new();
+        fs::write(vanilla.join("items.xml"), "<items/>").unwrap();
+
+        let mut linter = Linter::new();
+        linter.set_vanilla_config(&vanilla);
+
+        assert!(linter.lint_source("Config/items.xml", "<config/>").is_empty());
+        let diagnostics = linter.lint_source("Config/itemz.xml", "<config/>");
+        assert_eq!(
+            vec![Rule::UnknownConfigFile],
+            diagnostics.iter().map(|d| d.rule).collect::<Vec<_>>()
+        );
+    }
+}
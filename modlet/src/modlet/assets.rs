@@ -0,0 +1,102 @@
+use crate::error::ModletError;
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+
+/// Glob patterns selecting which of a modlet's files are discovered, relative to the modlet (e.g. `Config/*.xml`,
+/// `UIAtlases/**/*.png`).
+/// Hidden files and folders (e.g. `.git`, `Config/.keep`) and the modlet's own ModInfo.xml are never discovered.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(try_from = "Patterns"))]
+pub struct AssetRules {
+    /// Only files matching one of these are discovered (all files, if empty)
+    include: Vec<Pattern>,
+    /// Files matching one of these are never discovered
+    exclude: Vec<Pattern>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Patterns {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Patterns> for AssetRules {
+    type Error = glob::PatternError;
+
+    fn try_from(patterns: Patterns) -> Result<Self, Self::Error> {
+        let compile = |patterns: &[String]| patterns.iter().map(|p| Pattern::new(p)).collect::<Result<Vec<_>, _>>();
+
+        Ok(Self {
+            include: compile(&patterns.include)?,
+            exclude: compile(&patterns.exclude)?,
+        })
+    }
+}
+
+impl AssetRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only discovers files matching `pattern` (or any other included pattern)
+    pub fn include(mut self, pattern: &str) -> Result<Self, ModletError> {
+        self.include.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Never discovers files matching `pattern`
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, ModletError> {
+        self.exclude.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Whether a file (relative to its modlet) is discovered
+    pub fn allows(&self, path: &Path) -> bool {
+        let hidden = path
+            .iter()
+            .any(|component| component.to_string_lossy().starts_with('.'));
+        if hidden || path.as_os_str().eq_ignore_ascii_case("ModInfo.xml") {
+            return false;
+        }
+
+        let options = MatchOptions {
+            case_sensitive: false,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        let matches = |pattern: &Pattern| pattern.matches_path_with(path, options);
+
+        (self.include.is_empty() || self.include.iter().any(matches)) && !self.exclude.iter().any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rstest]
+    #[case::config("Config/items.xml", true)]
+    #[case::asset("UIAtlases/ItemIconAtlas/gunPistol.png", true)]
+    #[case::root_dll("MyHarmonyPatch.dll", true)]
+    #[case::hidden_file("Config/.keep", false)]
+    #[case::hidden_folder(".git/config", false)]
+    #[case::modinfo("modinfo.xml", false)]
+    #[case::excluded("Resources/source.psd", false)]
+    #[case::excluded_anywhere("Prefabs/source.psd", false)]
+    fn test_allows(#[case] path: &str, #[case] expected: bool) {
+        let rules = AssetRules::new().exclude("**/*.psd").unwrap();
+
+        assert_eq!(expected, rules.allows(Path::new(path)));
+    }
+
+    #[test]
+    fn test_include() {
+        let rules = AssetRules::new().include("Config/**/*").unwrap();
+
+        assert!(rules.allows(Path::new("Config/XUi/windows.xml")));
+        assert!(!rules.allows(Path::new("Resources/icons.unity3d")));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::error::ModletError;
+use csv::{ReaderBuilder, Terminator, WriterBuilder};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A `Localization.txt`: a CSV file with a `Key` column followed by a column per field or language
+/// (e.g. `Key,File,Type,UsedInMainMenu,NoTranslate,english,german`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Localization {
+    pub header: Vec<String>,
+    /// Each row's fields, in header order
+    pub rows: Vec<Vec<String>>,
+    /// The row of each key
+    keys: HashMap<String, usize>,
+}
+
+/// A key given different text by two of the merged files
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalizationConflict {
+    pub column: String,
+    pub key: String,
+    /// The file whose text replaced the earlier text
+    pub path: PathBuf,
+    pub previous: String,
+    pub text: String,
+}
+
+impl fmt::Display for LocalizationConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({}) is \"{}\", replacing \"{}\"",
+            self.path.display(),
+            self.key,
+            self.column,
+            self.text,
+            self.previous
+        )
+    }
+}
+
+impl Localization {
+    /// Parses a Localization.txt. Quoted fields may contain commas, quotes and newlines; short rows are padded.
+    ///
+    /// # Errors
+    ///
+    /// * If the file is not valid CSV
+    pub fn parse(path: impl AsRef<Path>, source: &str) -> Result<Self, ModletError> {
+        let invalid = |source| ModletError::InvalidLocalization {
+            path: path.as_ref().to_path_buf(),
+            source,
+        };
+        let mut reader = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(source.trim_start_matches('\u{feff}').as_bytes());
+
+        let header: Vec<String> = reader
+            .headers()
+            .map_err(invalid)?
+            .iter()
+            .map(|column| column.trim().to_string())
+            .collect();
+        let mut localization = Self {
+            header,
+            ..Self::default()
+        };
+        for record in reader.records() {
+            let record = record.map_err(invalid)?;
+            let mut row: Vec<String> = record.iter().map(str::to_string).collect();
+            if row.iter().all(|field| field.trim().is_empty()) {
+                continue;
+            }
+            row.resize(localization.header.len().max(row.len()), String::new());
+            localization.keys.insert(row[0].clone(), localization.rows.len());
+            localization.rows.push(row);
+        }
+
+        Ok(localization)
+    }
+
+    /// The index of a column, matching names case-insensitively (e.g. `English` and `english`)
+    fn column(&self, name: &str) -> Option<usize> {
+        self.header.iter().position(|column| column.eq_ignore_ascii_case(name))
+    }
+
+    /// Merges another file's rows into this one, mapping them onto the union of both headers.
+    /// A key already present takes the other file's text (as it would if the modlets were loaded separately, the
+    /// later one winning); each column whose text changes is reported.
+    pub fn merge(&mut self, other: &Localization, path: impl AsRef<Path>) -> Vec<LocalizationConflict> {
+        let columns: Vec<usize> = other
+            .header
+            .iter()
+            .map(|name| {
+                self.column(name).unwrap_or_else(|| {
+                    self.header.push(name.clone());
+                    self.header.len() - 1
+                })
+            })
+            .collect();
+        let width = self.header.len();
+        self.rows.iter_mut().for_each(|row| row.resize(width, String::new()));
+
+        let mut conflicts = Vec::new();
+        for other_row in &other.rows {
+            let key = &other_row[0];
+            let Some(&index) = self.keys.get(key) else {
+                let mut row = vec![String::new(); width];
+                for (&column, field) in columns.iter().zip(other_row) {
+                    row[column] = field.clone();
+                }
+                self.keys.insert(key.clone(), self.rows.len());
+                self.rows.push(row);
+                continue;
+            };
+
+            let row = &mut self.rows[index];
+            for (&column, field) in columns.iter().zip(other_row) {
+                if field.is_empty() || row[column] == *field {
+                    continue;
+                }
+                if !row[column].is_empty() {
+                    conflicts.push(LocalizationConflict {
+                        column: self.header[column].clone(),
+                        key: key.clone(),
+                        path: path.as_ref().to_path_buf(),
+                        previous: row[column].clone(),
+                        text: field.clone(),
+                    });
+                }
+                row[column] = field.clone();
+            }
+        }
+
+        conflicts
+    }
+
+    /// Writes the file as CSV with CRLF line endings, quoting only the fields which need it
+    ///
+    /// # Errors
+    ///
+    /// * If the CSV cannot be written
+    pub fn to_csv(&self) -> Result<String, ModletError> {
+        let invalid = |source| ModletError::InvalidLocalization {
+            path: PathBuf::from("Localization.txt"),
+            source,
+        };
+        let mut writer = WriterBuilder::new()
+            .flexible(true)
+            .terminator(Terminator::CRLF)
+            .from_writer(Vec::new());
+
+        writer.write_record(&self.header).map_err(invalid)?;
+        for row in &self.rows {
+            writer.write_record(row).map_err(invalid)?;
+        }
+        let bytes = writer
+            .into_inner()
+            .map_err(|err| ModletError::IoError(err.into_error()))?;
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Localization {
+        Localization::parse("Localization.txt", source).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let localization =
+            parse("\u{feff}Key,english\r\ngunPistol,\"Pistol, 9mm\"\r\nnote,\"Line one\nLine \"\"two\"\"\"\r\n");
+
+        assert_eq!(vec!["Key", "english"], localization.header);
+        assert_eq!(vec!["gunPistol", "Pistol, 9mm"], localization.rows[0]);
+        assert_eq!(vec!["note", "Line one\nLine \"two\""], localization.rows[1]);
+    }
+
+    #[test]
+    fn test_merge_columns() {
+        let mut localization = parse("Key,File,english\ngunPistol,items,Pistol\n");
+        let conflicts = localization.merge(&parse("Key,German,English\ngunRifle,Gewehr,Rifle\n"), "B");
+
+        assert!(conflicts.is_empty());
+        assert_eq!(vec!["Key", "File", "english", "German"], localization.header);
+        assert_eq!(vec!["gunPistol", "items", "Pistol", ""], localization.rows[0]);
+        assert_eq!(vec!["gunRifle", "", "Rifle", "Gewehr"], localization.rows[1]);
+        assert_eq!(
+            "Key,File,english,German\r\ngunPistol,items,Pistol,\r\ngunRifle,,Rifle,Gewehr\r\n",
+            localization.to_csv().unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case::same_text("Key,english\ngunPistol,Pistol\n", vec![], "Pistol")]
+    #[case::fills_empty("Key,english,german\ngunPistol,Pistol,Pistole\n", vec![], "Pistol")]
+    #[case::conflicting("Key,english\ngunPistol,Handgun\n", vec![("gunPistol", "Pistol", "Handgun")], "Handgun")]
+    fn test_merge_duplicates(#[case] other: &str, #[case] expected: Vec<(&str, &str, &str)>, #[case] english: &str) {
+        let mut localization = parse("Key,english\ngunPistol,Pistol\n");
+        let conflicts = localization.merge(&parse(other), "B");
+
+        assert_eq!(
+            expected,
+            conflicts
+                .iter()
+                .map(|c| (c.key.as_str(), c.previous.as_str(), c.text.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(1, localization.rows.len());
+        assert_eq!(english, localization.rows[0][1]);
+    }
+
+    #[test]
+    fn test_quoting_round_trip() {
+        let source = "Key,english\r\ngunPistol,\"Pistol, 9mm\"\r\nnote,\"Line one\nLine \"\"two\"\"\"\r\n";
+
+        assert_eq!(source, parse(source).to_csv().unwrap());
+    }
+}
@@ -1,30 +1,81 @@
+use crate::error::ModletError;
 use glob::glob;
 use modinfo::Modinfo;
 use rayon::prelude::*;
 use std::fmt;
 use std::{
     borrow::Cow,
-    fs::{self, File},
-    io::{self, prelude::*, Write},
+    fs,
+    io::Write,
     path::{Path, PathBuf},
 };
 
+mod assets;
+mod localization;
 mod modlet_xml;
-use modlet_xml::ModletXML;
+pub use assets::AssetRules;
+pub use localization::{Localization, LocalizationConflict};
+pub(crate) use modlet_xml::line_column;
+pub use modlet_xml::{
+    BranchKind, Command, Condition, ConditionalBranch, CsvInstruction, InstructionSet, InstructionSetBuilder,
+    ModletXML, Snippet, Span, PATCH_FILE_EXTENSIONS,
+};
+
+/// A file (other than a Localization.txt) which an earlier modlet already wrote, so this one's copy was skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetConflict {
+    /// The file, relative to the modlet
+    pub file: PathBuf,
+    /// The copy which was skipped
+    pub path: PathBuf,
+}
 
-const INCLUDE_EXTENSIONS: [&str; 3] = ["xml", "txt", "dll"];
+impl fmt::Display for AssetConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: skipped, {} was already written by an earlier modlet",
+            self.path.display(),
+            self.file.display()
+        )
+    }
+}
+
+/// The conflicts found by `Modlet::write_files`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileConflicts {
+    pub assets: Vec<AssetConflict>,
+    pub localization: Vec<LocalizationConflict>,
+}
+
+impl FileConflicts {
+    pub fn extend(&mut self, other: FileConflicts) {
+        self.assets.extend(other.assets);
+        self.localization.extend(other.localization);
+    }
+}
 
 /// Represents a modlet
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Modlet {
     pub files: Option<Vec<PathBuf>>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Modinfo::new"))]
     pub modinfo: Modinfo,
     pub path: PathBuf,
     pub xmls: Vec<ModletXML>,
 }
 
 impl Modlet {
-    pub fn new(path: impl AsRef<Path>) -> eyre::Result<Self> {
+    /// Loads a modlet, discovering every file in its tree (see `AssetRules`)
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, ModletError> {
+        Self::discover(path, &AssetRules::default())
+    }
+
+    /// Loads a modlet, discovering the files in its tree allowed by `rules`.
+    /// XML and patch files within `Config` are parsed; every other file (e.g. `Config/Localization.txt`,
+    /// `Resources/*.unity3d`, `UIAtlases/**/*.png`, root-level DLLs) is kept to be copied as-is.
+    pub fn discover(path: impl AsRef<Path>, rules: &AssetRules) -> Result<Self, ModletError> {
         let mut other_files = Vec::new();
         let path = path.as_ref().to_path_buf();
         let mut xmls = Vec::new();
@@ -33,20 +84,27 @@ impl Modlet {
         } else {
             Modinfo::new()
         };
-        let glob_pattern = path.join("Config/**/*");
+        let glob_pattern = path.join("**/*");
         for file in glob(glob_pattern.to_str().unwrap())? {
             let file = file?;
-            if file.is_dir() {
+            let Ok(relative) = file.strip_prefix(&path) else {
+                continue;
+            };
+            if file.is_dir() || !rules.allows(relative) {
                 continue;
             }
 
+            let in_config = relative
+                .iter()
+                .next()
+                .is_some_and(|dir| dir.eq_ignore_ascii_case("config"));
             let file_extension = file.extension().unwrap_or_default().to_ascii_lowercase();
-            if !INCLUDE_EXTENSIONS.contains(&file_extension.to_str().unwrap()) {
-                continue;
-            }
+            let file_extension = file_extension.to_str().unwrap_or_default();
 
-            if file_extension == "xml" {
+            if in_config && file_extension == "xml" {
                 xmls.push(ModletXML::new(file).load()?);
+            } else if in_config && PATCH_FILE_EXTENSIONS.contains(&file_extension) {
+                xmls.push(ModletXML::new(file).compile()?);
             } else {
                 other_files.push(file);
             }
@@ -66,6 +124,84 @@ impl Modlet {
         })
     }
 
+    /// Creates an empty modlet at `path`, to be populated with commands and saved
+    pub fn empty(path: impl AsRef<Path>) -> Self {
+        Self {
+            files: None,
+            modinfo: Modinfo::new(),
+            path: path.as_ref().to_path_buf(),
+            xmls: Vec::new(),
+        }
+    }
+
+    /// Returns the XML file patching `filename` (relative to `Config/`, e.g. `items.xml`), creating it if needed.
+    /// Structured patch files are never returned, as they are not written back by `save()`.
+    pub fn xml_mut(&mut self, filename: impl AsRef<Path>) -> &mut ModletXML {
+        let filename = filename.as_ref();
+        let index = match self.position(filename) {
+            Some(index) => index,
+            None => {
+                self.xmls
+                    .push(ModletXML::with_root(self.path.join("Config").join(filename), "config"));
+                self.xmls.len() - 1
+            }
+        };
+
+        &mut self.xmls[index]
+    }
+
+    /// The index of the XML file patching `filename`, if the modlet has one
+    fn position(&self, filename: &Path) -> Option<usize> {
+        self.xmls
+            .iter()
+            .position(|xml| is_xml(xml) && *xml.filename() == *filename)
+    }
+
+    /// Appends a command to the XML file patching `filename`
+    pub fn add_command(&mut self, filename: impl AsRef<Path>, command: Command) {
+        self.xml_mut(filename).commands.push(command);
+    }
+
+    /// Removes the commands in `filename` matching `predicate`, returning them.
+    /// Nothing is removed (and no file is created) if the modlet doesn't patch `filename`.
+    pub fn remove_commands(
+        &mut self,
+        filename: impl AsRef<Path>,
+        mut predicate: impl FnMut(&Command) -> bool,
+    ) -> Vec<Command> {
+        let Some(index) = self.position(filename.as_ref()) else {
+            return Vec::new();
+        };
+        let xml = &mut self.xmls[index];
+        let (removed, kept) = std::mem::take(&mut xml.commands)
+            .into_iter()
+            .partition(|command| !matches!(command, Command::StartTag(_)) && predicate(command));
+        xml.commands = kept;
+
+        removed
+    }
+
+    /// Replaces the first command in `filename` matching `predicate`, returning the command it replaced.
+    /// Returns `None` (without creating a file) if the modlet doesn't patch `filename`.
+    pub fn replace_command(
+        &mut self,
+        filename: impl AsRef<Path>,
+        predicate: impl FnMut(&Command) -> bool,
+        command: Command,
+    ) -> Option<Command> {
+        let index = self.position(filename.as_ref())?;
+        let xml = &mut self.xmls[index];
+        let index = xml.commands.iter().position(predicate)?;
+
+        Some(std::mem::replace(&mut xml.commands[index], command))
+    }
+
+    /// Writes every XML file back to the modlet's `Config/` tree.
+    /// Structured patch files (YAML/TOML) are left untouched.
+    pub fn save(&self) -> Result<(), ModletError> {
+        self.xmls.iter().filter(|xml| is_xml(xml)).try_for_each(ModletXML::save)
+    }
+
     pub fn xml_files(&self) -> Vec<Cow<Path>> {
         let mut xml_files = Vec::new();
         for xml in &self.xmls {
@@ -79,50 +215,206 @@ impl Modlet {
         self.path.file_name().unwrap_or_default().to_str().unwrap().into()
     }
 
+    /// Resolves a span back to the source lines it covers
+    ///
+    /// # Errors
+    ///
+    /// * If the span does not belong to one of this modlet's XML files
+    /// * If the file cannot be read, or has changed such that the span is out of range
+    pub fn resolve(&self, span: &Span) -> Result<Snippet, ModletError> {
+        if !self.xmls.iter().any(|xml| *xml.path == *span.path) {
+            return Err(ModletError::NotFound(span.path.to_path_buf()));
+        }
+
+        let source = fs::read_to_string(&span.path)?;
+        if source.get(span.range.clone()).is_none() {
+            return Err(ModletError::NotFound(span.path.to_path_buf()));
+        }
+
+        // Widen the range to cover complete lines
+        let start = source[..span.range.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = source[span.range.end..]
+            .find('\n')
+            .map_or(source.len(), |i| span.range.end + i);
+
+        Ok(Snippet {
+            column: span.column,
+            line: span.line,
+            path: span.path.to_path_buf(),
+            text: source[start..end].trim_end().to_string(),
+        })
+    }
+
     /// Write XML files
-    pub fn write_xmls(&self, writer: &mut quick_xml::Writer<impl Write>, filename: &Path) -> eyre::Result<()> {
+    pub fn write_xmls(&self, writer: &mut quick_xml::Writer<impl Write>, filename: &Path) -> Result<(), ModletError> {
         self.xmls
             .iter()
             .filter(|xml| *xml.filename() == *filename)
-            .try_for_each(|xml| xml.write(writer))?;
+            .try_for_each(|xml| xml.write_commands(writer))?;
 
         Ok(())
     }
 
-    /// Write non-xml files
-    pub fn write_files(&self, destination: &Path) -> eyre::Result<()> {
-        if let Some(files) = self.files.as_ref() {
-            files.into_par_iter().try_for_each(|file| -> eyre::Result<()> {
+    /// Copies the other (non-patch) files into `destination`, keeping their place in the modlet's tree.
+    /// A Localization.txt already written by an earlier modlet is merged with this one (see `Localization::merge`),
+    /// returning the keys whose text was replaced; any other file already written is left as it is, and returned.
+    pub fn write_files(&self, destination: &Path) -> Result<FileConflicts, ModletError> {
+        let Some(files) = self.files.as_ref() else {
+            return Ok(FileConflicts::default());
+        };
+
+        let conflicts = files
+            .into_par_iter()
+            .map(|file| -> Result<FileConflicts, ModletError> {
                 let file = file.strip_prefix(&self.path).unwrap();
                 let src = self.path.join(file);
                 let dst = destination.join(file);
                 if !dst.exists() {
                     fs::create_dir_all(dst.parent().unwrap())?;
                     fs::copy(src, dst)?;
-                // If the file is a localization file, and we've already copied it from an existing modlet above,
-                // strip the header and append the remaining lines to the existing file
-                } else if src.file_name().unwrap_or_default().to_ascii_lowercase() == "localization.txt" {
-                    let input = File::open(src)?;
-                    let reader = io::BufReader::new(input);
-                    let mut output = fs::OpenOptions::new().append(true).open(&dst)?;
-                    let mut writer = io::BufWriter::new(&mut output);
-
-                    for line in reader.lines().skip(1) {
-                        let line = line?;
-                        write!(writer, "{}\r\n", line)?; // We always write localization files with CRLF
-                    }
+
+                    return Ok(FileConflicts::default());
                 }
 
-                Ok(())
-            })?;
-        }
+                if !src
+                    .file_name()
+                    .unwrap_or_default()
+                    .eq_ignore_ascii_case("localization.txt")
+                {
+                    return Ok(FileConflicts {
+                        assets: vec![AssetConflict {
+                            file: file.to_path_buf(),
+                            path: src,
+                        }],
+                        ..Default::default()
+                    });
+                }
 
-        Ok(())
+                let mut localization = Localization::parse(&dst, &fs::read_to_string(&dst)?)?;
+                let conflicts = localization.merge(&Localization::parse(&src, &fs::read_to_string(&src)?)?, &src);
+                fs::write(&dst, localization.to_csv()?)?;
+
+                Ok(FileConflicts {
+                    localization: conflicts,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(conflicts
+            .into_iter()
+            .fold(FileConflicts::default(), |mut all, conflicts| {
+                all.extend(conflicts);
+                all
+            }))
     }
 }
 
+/// Whether the file was loaded from (or will be saved as) XML, rather than compiled from a patch file
+fn is_xml(xml: &ModletXML) -> bool {
+    xml.path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("xml"))
+}
+
 impl fmt::Display for Modlet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.name())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::TempDir;
+
+    #[test]
+    fn test_save() {
+        let dir = TempDir::new();
+        let path = dir.join("MyModlet");
+        let xpath = "/items/item[@name='gunPistol']/@value";
+        let mut modlet = Modlet::empty(&path);
+
+        modlet.add_command(
+            "items.xml",
+            Command::Set(InstructionSet::builder(xpath).text("1").build().unwrap()),
+        );
+        modlet.add_command(
+            "items.xml",
+            Command::Append(
+                InstructionSet::builder("/items")
+                    .xml("<item name=\"a\"/>")
+                    .build()
+                    .unwrap(),
+            ),
+        );
+        let replaced = modlet.replace_command(
+            "items.xml",
+            |command| matches!(command, Command::Set(_)),
+            Command::Set(InstructionSet::builder(xpath).text("2").build().unwrap()),
+        );
+        let removed = modlet.remove_commands("items.xml", |command| matches!(command, Command::Append(_)));
+        modlet.save().unwrap();
+
+        let saved = fs::read_to_string(path.join("Config/items.xml")).unwrap();
+        let reloaded = Modlet::new(&path).unwrap();
+
+        assert!(matches!(replaced, Some(Command::Set(_))));
+        assert_eq!(1, removed.len());
+        assert_eq!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<config>\n    <set xpath=\"/items/item[@name='gunPistol']/@value\">2</set>\n</config>\n",
+            saved
+        );
+        assert_eq!(2, reloaded.xmls[0].commands.len());
+    }
+
+    #[test]
+    fn test_write_files() {
+        let dir = TempDir::new();
+        let destination = dir.join("Bundle");
+        let modlets: Vec<Modlet> = ["ModA", "ModB"]
+            .into_iter()
+            .map(|name| {
+                let mut modlet = Modlet::empty(dir.join(name));
+                let files = [
+                    ("UIAtlases/ItemIconAtlas/icon.png", name.to_string()),
+                    ("Config/Localization.txt", format!("Key,english\nitem,{name}\n")),
+                ]
+                .map(|(file, contents)| {
+                    let path = modlet.path.join(file);
+                    fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    fs::write(&path, contents).unwrap();
+                    path
+                });
+                modlet.files = Some(files.to_vec());
+                modlet
+            })
+            .collect();
+
+        let first = modlets[0].write_files(&destination).unwrap();
+        let second = modlets[1].write_files(&destination).unwrap();
+        let icon = Path::new("UIAtlases/ItemIconAtlas/icon.png");
+
+        assert_eq!(FileConflicts::default(), first);
+        assert_eq!(
+            vec![AssetConflict {
+                file: icon.to_path_buf(),
+                path: modlets[1].path.join(icon),
+            }],
+            second.assets
+        );
+        assert_eq!(1, second.localization.len());
+        assert_eq!("ModA", fs::read_to_string(destination.join(icon)).unwrap());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let mut modlet = Modlet::empty("MyModlet");
+        let replaced = modlet.replace_command("items.xml", |_| true, Command::Comment("a".into(), None));
+        let removed = modlet.remove_commands("items.xml", |_| true);
+
+        assert!(replaced.is_none());
+        assert!(removed.is_empty());
+        assert!(modlet.xml_files().is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use super::{CsvInstruction, InstructionSet};
+use crate::{error::ModletError, xpath};
+use quick_xml::{
+    events::{BytesText, Event},
+    Reader,
+};
+
+/// Builds an `InstructionSet`, validating its xpath (and any child XML) on `build()`
+///
+/// ```
+/// use modlet::modlet::{Command, InstructionSet};
+///
+/// let command = Command::SetAttribute(
+///     InstructionSet::builder("/items/item[@name='gunPistol']")
+///         .attribute("Tags")
+///         .text("weapon,ranged")
+///         .build()?,
+/// );
+/// # Ok::<(), modlet::ModletError>(())
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct InstructionSetBuilder {
+    attribute: Option<String>,
+    csv_op: Option<CsvInstruction>,
+    values: Vec<Value>,
+    xpath: String,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Xml(String),
+}
+
+impl InstructionSetBuilder {
+    pub fn new(xpath: impl Into<String>) -> Self {
+        Self {
+            xpath: xpath.into(),
+            ..Self::default()
+        }
+    }
+
+    /// The attribute to set (for `setattribute`)
+    pub fn attribute(mut self, name: impl Into<String>) -> Self {
+        self.attribute = Some(name.into());
+        self
+    }
+
+    /// The csv operation (for `csv`)
+    pub fn csv_op(mut self, csv_op: CsvInstruction) -> Self {
+        self.csv_op = Some(csv_op);
+        self
+    }
+
+    /// The csv operation and its entries, which are joined by the operation's delimiter (for `csv`)
+    pub fn csv<S: AsRef<str>>(mut self, csv_op: CsvInstruction, values: impl IntoIterator<Item = S>) -> Self {
+        let delim = csv_op.delim().to_string();
+        let values = values
+            .into_iter()
+            .map(|value| value.as_ref().to_owned())
+            .collect::<Vec<_>>();
+
+        self.csv_op = Some(csv_op);
+        self.values.push(Value::Text(values.join(&delim)));
+        self
+    }
+
+    /// Appends (unescaped) text, such as the value of a `set`
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.values.push(Value::Text(text.into()));
+        self
+    }
+
+    /// Appends child elements (for `append`, `insertAfter` and `insertBefore`), written as XML
+    pub fn xml(mut self, xml: impl Into<String>) -> Self {
+        self.values.push(Value::Xml(xml.into()));
+        self
+    }
+
+    /// # Errors
+    ///
+    /// * If the xpath is not valid
+    /// * If any child XML is not valid
+    pub fn build(self) -> Result<InstructionSet, ModletError> {
+        xpath::parse(&self.xpath).map_err(|source| ModletError::InvalidXPath {
+            xpath: self.xpath.clone(),
+            source,
+        })?;
+
+        let mut values = Vec::new();
+        for value in &self.values {
+            match value {
+                Value::Text(text) => values.push(Event::Text(BytesText::new(text).into_owned())),
+                Value::Xml(xml) => {
+                    let mut reader = Reader::from_str(xml);
+                    reader.trim_text(true);
+                    loop {
+                        match reader.read_event().map_err(ModletError::InvalidXml)? {
+                            Event::Eof => break,
+                            event => values.push(event.into_owned()),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(InstructionSet {
+            attribute: self.attribute.map(String::into_bytes),
+            csv_op: self.csv_op,
+            span: None,
+            values,
+            xpath: self.xpath.into_bytes(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let is = InstructionSetBuilder::new("/items")
+            .xml("<item name=\"a\"><property name=\"b\"/></item>")
+            .build()
+            .unwrap();
+
+        assert_eq!(b"/items".to_vec(), is.xpath);
+        assert_eq!(3, is.values.len());
+    }
+
+    #[test]
+    fn test_csv() {
+        let is = InstructionSetBuilder::new("/items/item/@Tags")
+            .csv(CsvInstruction::Add('|'), ["a", "b,c"])
+            .build()
+            .unwrap();
+
+        assert_eq!("a|b,c", is.text());
+        assert_eq!(vec!["a", "b,c"], is.csv_values());
+    }
+
+    #[rstest]
+    #[case::xpath(InstructionSetBuilder::new("/items/item[@name='a'"), "Invalid xpath")]
+    #[case::xml(InstructionSetBuilder::new("/items").xml("<item></property>"), "Invalid XML")]
+    fn test_build_error(#[case] builder: InstructionSetBuilder, #[case] expected: &str) {
+        assert!(builder.build().unwrap_err().to_string().starts_with(expected));
+    }
+}
@@ -1,5 +1,14 @@
+use super::{builder::InstructionSetBuilder, conditional::ConditionalBranch, span::Span};
+use crate::{
+    error::{ModletError, XPathError},
+    xpath::{self, LocationPath},
+};
 use convert_case::{Case, Casing};
-use quick_xml::events::{BytesText, Event};
+use quick_xml::{
+    escape::partial_escape,
+    events::{attributes::Attribute, BytesText, Event},
+    name::QName,
+};
 use std::{
     borrow::Cow,
     fmt::{Display, Formatter},
@@ -15,6 +24,8 @@ pub const EMPTY_COMMANDS: [&str; 2] = ["remove", "removeattribute"];
 pub const TEXT_COMMANDS: [&str; 3] = ["csv", "set", "setattribute"];
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "op", content = "delim", rename_all = "lowercase"))]
 pub enum CsvInstruction {
     Add(char),
     Remove(char),
@@ -37,10 +48,16 @@ impl CsvInstruction {
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InstructionSet {
+    #[cfg_attr(feature = "serde", serde(with = "super::serialize::option_bytes"))]
     pub attribute: Option<Vec<u8>>,
     pub csv_op: Option<CsvInstruction>,
+    /// Where the command was found, if it was loaded from a file
+    pub span: Option<Span>,
+    #[cfg_attr(feature = "serde", serde(with = "super::serialize::events"))]
     pub values: Vec<Event<'static>>,
+    #[cfg_attr(feature = "serde", serde(with = "super::serialize::bytes"))]
     pub xpath: Vec<u8>,
 }
 
@@ -49,6 +66,53 @@ impl InstructionSet {
         Self::default()
     }
 
+    /// Starts building an instruction set targeting `xpath`
+    pub fn builder(xpath: impl Into<String>) -> InstructionSetBuilder {
+        InstructionSetBuilder::new(xpath)
+    }
+
+    /// Returns the unescaped text content of this instruction set
+    pub fn text(&self) -> String {
+        self.values
+            .iter()
+            .filter_map(|e| match e {
+                Event::Text(text) => text.unescape().ok().map(|text| text.into_owned()),
+                Event::CData(cdata) => str::from_utf8(cdata).ok().map(str::to_owned),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the entries of a csv command, split on its declared delimiter (`,` if none is declared).
+    /// Entries are trimmed, and empty entries are skipped.
+    pub fn csv_values(&self) -> Vec<String> {
+        let delim = self.csv_op.as_ref().map_or(',', |csv_op| *csv_op.delim());
+
+        self.text()
+            .split(delim)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Replaces the values with the given entries, joined by the declared delimiter (`,` if none is declared)
+    pub fn set_csv_values<S: AsRef<str>>(&mut self, values: impl IntoIterator<Item = S>) {
+        let delim = self.csv_op.as_ref().map_or(',', |csv_op| *csv_op.delim()).to_string();
+        let text = values
+            .into_iter()
+            .map(|value| value.as_ref().to_owned())
+            .collect::<Vec<_>>()
+            .join(&delim);
+
+        self.values = vec![Event::Text(BytesText::new(&text).into_owned())];
+    }
+
+    /// Parses the xpath
+    pub fn location_path(&self) -> Result<LocationPath, XPathError> {
+        xpath::parse(&String::from_utf8_lossy(&self.xpath))
+    }
+
     fn values_to_strings(&self) -> Vec<String> {
         self.values
             .iter()
@@ -56,16 +120,42 @@ impl InstructionSet {
             .collect()
     }
 
-    fn xpath_attribute(&self) -> (&[u8], &[u8]) {
-        (b"xpath".as_ref(), self.xpath.as_slice())
+    fn xpath_attribute(&self) -> Attribute<'static> {
+        attribute("xpath", &self.xpath)
+    }
+
+    /// Writes the values verbatim, so that text is not escaped a second time and CDATA is kept intact
+    fn write_values(&self, writer: &mut quick_xml::Writer<impl Write>) -> Result<(), ModletError> {
+        self.values.iter().try_for_each(|event| writer.write_event(event))?;
+
+        Ok(())
+    }
+}
+
+/// Builds an attribute from an unescaped value.
+/// Single quotes are left alone, as they are used throughout xpaths.
+fn attribute(key: &'static str, value: &[u8]) -> Attribute<'static> {
+    Attribute {
+        key: QName(key.as_bytes()),
+        value: partial_escape(String::from_utf8_lossy(value).as_ref())
+            .replace('"', "&quot;")
+            .into_bytes()
+            .into(),
     }
 }
 
 /// Represents a modlet command instruction
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "command", content = "args", rename_all = "camelCase")
+)]
 pub enum Command {
     Append(InstructionSet),
-    Comment(Cow<'static, str>),
+    /// A comment, and where it was found (if it was loaded from a file)
+    Comment(Cow<'static, str>, Option<Span>),
+    Conditional(Vec<ConditionalBranch>),
     Csv(InstructionSet),
     InsertAfter(InstructionSet),
     InsertBefore(InstructionSet),
@@ -75,7 +165,13 @@ pub enum Command {
     Set(InstructionSet),
     SetAttribute(InstructionSet),
     StartTag(Option<String>),
-    Unknown(Cow<'static, str>),
+    /// An unrecognised element: its tag, its raw events (written back unchanged), and where it was found (if it was
+    /// loaded from a file)
+    Unknown(
+        Cow<'static, str>,
+        #[cfg_attr(feature = "serde", serde(with = "super::serialize::events"))] Vec<Event<'static>>,
+        Option<Span>,
+    ),
 }
 
 impl Command {
@@ -83,7 +179,8 @@ impl Command {
         let match_string = input_str.to_case(Case::Flat);
         match match_string.as_str() {
             "append" => Command::Append(InstructionSet::new()),
-            "comment" => Command::Comment(Cow::Owned(String::new())),
+            "comment" => Command::Comment(Cow::Owned(String::new()), None),
+            "conditional" => Command::Conditional(Vec::new()),
             "csv" => Command::Csv(InstructionSet::new()),
             "insertafter" => Command::InsertAfter(InstructionSet::new()),
             "insertbefore" => Command::InsertBefore(InstructionSet::new()),
@@ -93,14 +190,15 @@ impl Command {
             "set" => Command::Set(InstructionSet::new()),
             "setattribute" => Command::SetAttribute(InstructionSet::new()),
             "starttag" => Command::StartTag(None),
-            tag => Command::Unknown(Cow::Owned(tag.to_string())),
+            tag => Command::Unknown(Cow::Owned(tag.to_string()), Vec::new(), None),
         }
     }
 
     pub fn set(self, instruction_set: InstructionSet) -> Self {
         match self {
             Command::Append(_) => Self::Append(instruction_set),
-            Command::Comment(_) => Self::Comment(Cow::Owned(instruction_set.values_to_strings().join(","))),
+            Command::Comment(_, span) => Self::Comment(Cow::Owned(instruction_set.values_to_strings().join(",")), span),
+            Command::Conditional(branches) => Self::Conditional(branches),
             Command::Csv(_) => Self::Csv(instruction_set),
             Command::InsertAfter(_) => Self::InsertAfter(instruction_set),
             Command::InsertBefore(_) => Self::InsertBefore(instruction_set),
@@ -110,39 +208,85 @@ impl Command {
             Command::Set(_) => Self::Set(instruction_set),
             Command::SetAttribute(_) => Self::SetAttribute(instruction_set),
             Command::StartTag(_) => Self::StartTag(None),
-            Command::Unknown(tag) => Self::Unknown(tag),
+            Command::Unknown(tag, events, span) => Self::Unknown(tag, events, span),
+        }
+    }
+
+    /// Returns the instruction set of a patch command, or `None` for non-patch commands (comments, etc.)
+    pub fn instructions(&self) -> Option<&InstructionSet> {
+        match self {
+            Command::Append(is)
+            | Command::Csv(is)
+            | Command::InsertAfter(is)
+            | Command::InsertBefore(is)
+            | Command::Remove(is)
+            | Command::RemoveAttribute(is)
+            | Command::Set(is)
+            | Command::SetAttribute(is) => Some(is),
+            _ => None,
+        }
+    }
+
+    /// Returns the modlet named by an `<!-- Included from ... -->` comment, which `package` writes before each
+    /// modlet's commands in a bundle
+    pub fn included_from(&self) -> Option<&str> {
+        match self {
+            Command::Comment(comment, _) => comment.trim().strip_prefix("Included from "),
+            _ => None,
         }
     }
 
-    pub fn write(&self, writer: &mut quick_xml::Writer<impl Write>) -> eyre::Result<()> {
+    /// Returns where the command was found, if it was loaded from a file
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Command::Comment(_, span) | Command::Unknown(_, _, span) => span.as_ref(),
+            Command::Conditional(branches) => branches.first().and_then(|branch| branch.span.as_ref()),
+            command => command.instructions().and_then(|is| is.span.as_ref()),
+        }
+    }
+
+    pub fn write(&self, writer: &mut quick_xml::Writer<impl Write>) -> Result<(), ModletError> {
         match self {
             Command::Append(is) | Command::InsertAfter(is) | Command::InsertBefore(is) => {
                 writer
                     .create_element(&self.to_string())
                     .with_attribute(is.xpath_attribute())
-                    .write_inner_content(move |writer| {
-                        for event in &is.values {
-                            writer.write_event(event)?;
-                        }
-                        Ok::<(), eyre::Error>(())
-                    })?;
+                    .write_inner_content(|writer| is.write_values(writer))?;
             }
-            Command::Comment(comment) => {
+            Command::Comment(comment, _) => {
                 let comment = BytesText::from_escaped(comment.clone());
                 writer.write_event(Event::Comment(comment))?
             }
+            Command::Conditional(branches) => {
+                writer
+                    .create_element(&self.to_string())
+                    .write_inner_content(move |writer| {
+                        for branch in branches {
+                            let mut element = writer.create_element(branch.kind.as_ref());
+                            if let Some(condition) = &branch.condition {
+                                element = element.with_attribute(attribute("cond", condition.to_string().as_bytes()));
+                            }
+                            if branch.commands.is_empty() {
+                                element.write_empty()?;
+                            } else {
+                                element.write_inner_content(|writer| {
+                                    branch.commands.iter().try_for_each(|command| command.write(writer))
+                                })?;
+                            }
+                        }
+                        Ok::<(), ModletError>(())
+                    })?;
+            }
             Command::Csv(is) => {
+                let csv_op = is.csv_op.as_ref().ok_or_else(|| self.missing_attribute("op"))?;
                 writer
                     .create_element(&self.to_string())
                     .with_attributes([
                         is.xpath_attribute(),
-                        (
-                            b"delim".as_ref(),
-                            is.csv_op.as_ref().unwrap().delim().to_string().as_bytes(),
-                        ),
-                        (b"op".as_ref(), is.csv_op.as_ref().unwrap().op().as_bytes()),
+                        attribute("delim", csv_op.delim().to_string().as_bytes()),
+                        attribute("op", csv_op.op().as_bytes()),
                     ])
-                    .write_text_content(BytesText::new(is.values_to_strings().join(",").as_ref()))?;
+                    .write_inner_content(|writer| is.write_values(writer))?;
             }
             Command::Remove(is) | Command::RemoveAttribute(is) => {
                 writer
@@ -154,30 +298,36 @@ impl Command {
                 writer
                     .create_element(&self.to_string())
                     .with_attribute(is.xpath_attribute())
-                    .write_text_content(BytesText::new(is.values_to_strings().join(",").as_ref()))?;
+                    .write_inner_content(|writer| is.write_values(writer))?;
             }
             Command::SetAttribute(is) => {
+                let name = is.attribute.as_ref().ok_or_else(|| self.missing_attribute("name"))?;
                 writer
                     .create_element(&self.to_string())
-                    .with_attributes([
-                        is.xpath_attribute(),
-                        (b"name".as_ref(), is.attribute.as_ref().unwrap().to_vec().as_slice()),
-                    ])
-                    .write_text_content(BytesText::new(is.values_to_strings().join(",").as_ref()))?;
+                    .with_attributes([is.xpath_attribute(), attribute("name", name)])
+                    .write_inner_content(|writer| is.write_values(writer))?;
             }
-            Command::StartTag(_) => (),
-            _ => (),
+            Command::Unknown(_, events, _) => events.iter().try_for_each(|event| writer.write_event(event))?,
+            Command::NoOp | Command::StartTag(_) => (),
         }
 
         Ok(())
     }
+
+    fn missing_attribute(&self, attribute: &'static str) -> ModletError {
+        ModletError::MissingAttribute {
+            command: self.to_string(),
+            attribute,
+        }
+    }
 }
 
 impl AsRef<str> for Command {
     fn as_ref(&self) -> &str {
         match self {
             Command::Append(_) => "append",
-            Command::Comment(_) => "comment",
+            Command::Comment(..) => "comment",
+            Command::Conditional(_) => "conditional",
             Command::Csv(_) => "csv",
             Command::InsertAfter(_) => "insertafter",
             Command::InsertBefore(_) => "insertbefore",
@@ -187,7 +337,7 @@ impl AsRef<str> for Command {
             Command::Set(_) => "set",
             Command::SetAttribute(_) => "setattribute",
             Command::StartTag(_) => "starttag",
-            Command::Unknown(_) => "unknown",
+            Command::Unknown(..) => "unknown",
         }
     }
 }
@@ -196,17 +346,18 @@ impl Display for Command {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Command::Append(_) => write!(f, "append"),
-            Command::Comment(_) => write!(f, "comment"),
+            Command::Comment(..) => write!(f, "comment"),
+            Command::Conditional(_) => write!(f, "conditional"),
             Command::Csv(_) => write!(f, "csv"),
             Command::InsertAfter(_) => write!(f, "insertAfter"),
             Command::InsertBefore(_) => write!(f, "insertBefore"),
             Command::NoOp => write!(f, "no_op"),
             Command::Remove(_) => write!(f, "remove"),
-            Command::RemoveAttribute(_) => write!(f, "removeAttribute"),
+            Command::RemoveAttribute(_) => write!(f, "removeattribute"),
             Command::Set(_) => write!(f, "set"),
-            Command::SetAttribute(_) => write!(f, "setAttribute"),
+            Command::SetAttribute(_) => write!(f, "setattribute"),
             Command::StartTag(_) => write!(f, "start_tag"),
-            Command::Unknown(tag) => write!(f, "{tag}"),
+            Command::Unknown(tag, ..) => write!(f, "{tag}"),
         }
     }
 }
@@ -223,9 +374,9 @@ mod tests {
     // mod fixtures;
     #[rstest]
     #[case::with_append("append", Command::Append(instruction_set()))]
-    #[case::with_comment("comment", Command::Comment(Cow::Owned(String::new())))]
+    #[case::with_comment("comment", Command::Comment(Cow::Owned(String::new()), None))]
     #[case::with_csv("csv", Command::Csv(instruction_set()))]
-    #[case::with_unknown("foo", Command::Unknown(Cow::Owned("foo".to_string())))]
+    #[case::with_unknown("foo", Command::Unknown(Cow::Owned("foo".to_string()), Vec::new(), None))]
     fn test_parse(#[case] input: &str, #[case] expected: Command) {
         assert_eq!(expected, Command::parse(input));
     }
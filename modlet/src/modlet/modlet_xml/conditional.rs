@@ -0,0 +1,375 @@
+use super::{command::Command, span::Span};
+use crate::error::ConditionError;
+use std::{
+    fmt::{Display, Formatter},
+    str,
+};
+
+/// The kind of branch within a `<conditional>` block
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum BranchKind {
+    Else,
+    ElseIf,
+    If,
+}
+
+impl BranchKind {
+    pub fn parse(tag_name: &str) -> Option<Self> {
+        match tag_name.to_ascii_lowercase().as_str() {
+            "if" => Some(BranchKind::If),
+            "elseif" => Some(BranchKind::ElseIf),
+            "else" => Some(BranchKind::Else),
+            _ => None,
+        }
+    }
+}
+
+impl AsRef<str> for BranchKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            BranchKind::Else => "else",
+            BranchKind::ElseIf => "elseif",
+            BranchKind::If => "if",
+        }
+    }
+}
+
+/// A single `<if>`, `<elseif>` or `<else>` branch of a `<conditional>` block
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConditionalBranch {
+    pub commands: Vec<Command>,
+    /// The parsed `cond` attribute (`None` for `<else>`)
+    pub condition: Option<Condition>,
+    pub kind: BranchKind,
+    /// Where the branch was found, if it was loaded from a file
+    pub span: Option<Span>,
+}
+
+/// A parsed `cond` expression, such as `mod_loaded('OtherMod') and not mod_loaded('ThirdMod')`
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
+pub enum Condition {
+    And(Box<Condition>, Box<Condition>),
+    /// A function call, such as `mod_loaded('OtherMod')`. Arguments are kept as written (including quotes).
+    Call(String, Vec<String>),
+    /// A comparison, such as `game_version >= 21.1`. Operands are kept as written (including quotes).
+    Compare(String, String, String),
+    Not(Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// # Errors
+    ///
+    /// * If the condition is not valid
+    pub fn parse(input: &str) -> Result<Self, ConditionError> {
+        let tokens = tokenize(input)?;
+        let mut pos = 0;
+        let condition = parse_or(&tokens, &mut pos)?;
+
+        match tokens.get(pos) {
+            Some(token) => Err(ConditionError::new(format!(
+                "Unexpected '{token}' in condition \"{input}\""
+            ))),
+            None => Ok(condition),
+        }
+    }
+
+    /// Evaluates the condition, given the names of the loaded mods.
+    /// Only `mod_loaded()` is understood; anything else evaluates to `false`.
+    pub fn evaluate(&self, loaded_mods: &[impl AsRef<str>]) -> bool {
+        match self {
+            Condition::And(lhs, rhs) => lhs.evaluate(loaded_mods) && rhs.evaluate(loaded_mods),
+            Condition::Or(lhs, rhs) => lhs.evaluate(loaded_mods) || rhs.evaluate(loaded_mods),
+            Condition::Not(condition) => !condition.evaluate(loaded_mods),
+            Condition::Call(name, args) if name == "mod_loaded" => args.iter().all(|arg| {
+                let arg = unquote(arg);
+                loaded_mods.iter().any(|name| name.as_ref() == arg)
+            }),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::And(lhs, rhs) => write!(
+                f,
+                "{} and {}",
+                Group(lhs, matches!(**lhs, Condition::Or(..))),
+                Group(rhs, matches!(**rhs, Condition::And(..) | Condition::Or(..)))
+            ),
+            Condition::Or(lhs, rhs) => write!(f, "{lhs} or {}", Group(rhs, matches!(**rhs, Condition::Or(..)))),
+            Condition::Not(condition) => write!(
+                f,
+                "not {}",
+                Group(
+                    condition,
+                    matches!(
+                        **condition,
+                        Condition::And(..) | Condition::Or(..) | Condition::Compare(..)
+                    )
+                )
+            ),
+            Condition::Call(name, args) => write!(f, "{name}({})", args.join(", ")),
+            Condition::Compare(lhs, op, rhs) => write!(f, "{lhs} {op} {rhs}"),
+        }
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.to_string()
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = ConditionError;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        Condition::parse(&input)
+    }
+}
+
+/// Displays an operand, in parentheses when it would otherwise parse back differently (e.g. the `and` of
+/// `not (A and B)`, or the right-hand `or` of `A or (B or C)`)
+struct Group<'a>(&'a Condition, bool);
+
+impl Display for Group<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Group(condition, true) => write!(f, "({condition})"),
+            Group(condition, false) => write!(f, "{condition}"),
+        }
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches(|c| c == '\'' || c == '"')
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        let token = match ch {
+            c if c.is_whitespace() => continue,
+            '(' | ')' | ',' => ch.to_string(),
+            '!' | '=' | '<' | '>' => match chars.next_if_eq(&'=') {
+                Some(_) => format!("{ch}="),
+                None => ch.to_string(),
+            },
+            '\'' | '"' => {
+                let mut literal = ch.to_string();
+                loop {
+                    match chars.next() {
+                        Some(c) => {
+                            literal.push(c);
+                            if c == ch {
+                                break;
+                            }
+                        }
+                        None => {
+                            return Err(ConditionError::new(format!(
+                                "Unterminated string in condition \"{input}\""
+                            )))
+                        }
+                    }
+                }
+                literal
+            }
+            _ => {
+                let mut word = ch.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"()!=<>,'\"".contains(*c)) {
+                    word.push(c);
+                }
+                word
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn expect(tokens: &[String], pos: &mut usize, expected: &str) -> Result<(), ConditionError> {
+    match tokens.get(*pos) {
+        Some(token) if token == expected => {
+            *pos += 1;
+            Ok(())
+        }
+        Some(token) => Err(ConditionError::new(format!(
+            "Expected '{expected}' in condition, found '{token}'"
+        ))),
+        None => Err(ConditionError::new(format!(
+            "Expected '{expected}' in condition, found end of input"
+        ))),
+    }
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> Result<Condition, ConditionError> {
+    let mut lhs = parse_and(tokens, pos)?;
+    while tokens.get(*pos).is_some_and(|token| token.eq_ignore_ascii_case("or")) {
+        *pos += 1;
+        lhs = Condition::Or(Box::new(lhs), Box::new(parse_and(tokens, pos)?));
+    }
+
+    Ok(lhs)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> Result<Condition, ConditionError> {
+    let mut lhs = parse_unary(tokens, pos)?;
+    while tokens.get(*pos).is_some_and(|token| token.eq_ignore_ascii_case("and")) {
+        *pos += 1;
+        lhs = Condition::And(Box::new(lhs), Box::new(parse_unary(tokens, pos)?));
+    }
+
+    Ok(lhs)
+}
+
+fn parse_unary(tokens: &[String], pos: &mut usize) -> Result<Condition, ConditionError> {
+    match tokens.get(*pos).map(String::as_str) {
+        Some("!") => {
+            *pos += 1;
+            Ok(Condition::Not(Box::new(parse_unary(tokens, pos)?)))
+        }
+        Some(token) if token.eq_ignore_ascii_case("not") => {
+            *pos += 1;
+            Ok(Condition::Not(Box::new(parse_unary(tokens, pos)?)))
+        }
+        Some("(") => {
+            *pos += 1;
+            let condition = parse_or(tokens, pos)?;
+            expect(tokens, pos, ")")?;
+            Ok(condition)
+        }
+        Some(_) => parse_primary(tokens, pos),
+        None => Err(ConditionError::new("Expected a condition, found end of input")),
+    }
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_primary(tokens: &[String], pos: &mut usize) -> Result<Condition, ConditionError> {
+    let lhs = tokens[*pos].clone();
+    if !is_identifier(&lhs) {
+        return Err(ConditionError::new(format!("Expected a condition, found '{lhs}'")));
+    }
+    *pos += 1;
+
+    if tokens.get(*pos).map(String::as_str) == Some("(") {
+        *pos += 1;
+        let mut args = Vec::new();
+        while tokens.get(*pos).map(String::as_str) != Some(")") {
+            if !args.is_empty() {
+                expect(tokens, pos, ",")?;
+            }
+            match tokens.get(*pos) {
+                Some(arg) => args.push(arg.clone()),
+                None => return Err(ConditionError::new("Expected ')' in condition, found end of input")),
+            }
+            *pos += 1;
+        }
+        expect(tokens, pos, ")")?;
+
+        return Ok(Condition::Call(lhs, args));
+    }
+
+    match tokens.get(*pos).map(String::as_str) {
+        Some(op @ ("=" | "==" | "!=" | "<" | "<=" | ">" | ">=")) => {
+            let op = op.to_string();
+            *pos += 1;
+            let rhs = tokens
+                .get(*pos)
+                .cloned()
+                .ok_or_else(|| ConditionError::new(format!("Expected a value after '{op}' in condition")))?;
+            *pos += 1;
+            Ok(Condition::Compare(lhs, op, rhs))
+        }
+        // A bare identifier, treated as a function call without arguments
+        _ => Ok(Condition::Call(lhs, Vec::new())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rstest]
+    #[case::mod_loaded("mod_loaded('OtherMod')", "mod_loaded('OtherMod')")]
+    #[case::not("!mod_loaded(\"OtherMod\")", "not mod_loaded(\"OtherMod\")")]
+    #[case::and_or(
+        "mod_loaded('A') and (mod_loaded('B') or mod_loaded('C'))",
+        "mod_loaded('A') and (mod_loaded('B') or mod_loaded('C'))"
+    )]
+    #[case::compare("game_version>=21.1", "game_version >= 21.1")]
+    #[case::not_and(
+        "not (mod_loaded('A') and mod_loaded('B'))",
+        "not (mod_loaded('A') and mod_loaded('B'))"
+    )]
+    fn test_parse_round_trip(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(expected, Condition::parse(input).unwrap().to_string());
+    }
+
+    #[rstest]
+    #[case::unterminated("mod_loaded('A)", "Unterminated string in condition \"mod_loaded('A)\"")]
+    #[case::unclosed("(mod_loaded('A')", "Expected ')' in condition, found end of input")]
+    #[case::close_paren(")", "Expected a condition, found ')'")]
+    #[case::dangling_and("mod_loaded('A') and )", "Expected a condition, found ')'")]
+    #[case::literal("'A'", "Expected a condition, found ''A''")]
+    #[case::trailing(
+        "mod_loaded('A') mod_loaded('B')",
+        "Unexpected 'mod_loaded' in condition \"mod_loaded('A') mod_loaded('B')\""
+    )]
+    fn test_parse_error(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(ConditionError::new(expected), Condition::parse(input).unwrap_err());
+    }
+
+    fn loaded(name: &str) -> Box<Condition> {
+        Box::new(Condition::Call("mod_loaded".to_string(), vec![format!("'{name}'")]))
+    }
+
+    #[rstest]
+    #[case::not_and(Condition::Not(Box::new(Condition::And(loaded("A"), loaded("B")))))]
+    #[case::not_or(Condition::Not(Box::new(Condition::Or(loaded("A"), loaded("B")))))]
+    #[case::not_compare(Condition::Not(Box::new(Condition::Compare("game_version".into(), ">=".into(), "21".into()))))]
+    #[case::not_not(Condition::Not(Box::new(Condition::Not(loaded("A")))))]
+    #[case::and_or(Condition::And(Box::new(Condition::Or(loaded("A"), loaded("B"))), loaded("C")))]
+    #[case::or_and(Condition::Or(loaded("A"), Box::new(Condition::And(loaded("B"), loaded("C")))))]
+    #[case::and_not_or(Condition::And(
+        loaded("A"),
+        Box::new(Condition::Not(Box::new(Condition::Or(loaded("B"), loaded("C")))))
+    ))]
+    #[case::right_nested_and(Condition::And(loaded("A"), Box::new(Condition::And(loaded("B"), loaded("C")))))]
+    #[case::right_nested_or(Condition::Or(loaded("A"), Box::new(Condition::Or(loaded("B"), loaded("C")))))]
+    #[case::nested(Condition::Or(
+        Box::new(Condition::Not(Box::new(Condition::And(
+            loaded("A"),
+            Box::new(Condition::Or(loaded("B"), loaded("C")))
+        )))),
+        Box::new(Condition::And(Box::new(Condition::Not(loaded("D"))), loaded("E")))
+    ))]
+    fn test_display_round_trip(#[case] condition: Condition) {
+        assert_eq!(condition, Condition::parse(&condition.to_string()).unwrap());
+    }
+
+    #[rstest]
+    #[case("mod_loaded('A')", true)]
+    #[case("not mod_loaded('A')", false)]
+    #[case("mod_loaded('A') and mod_loaded('B')", false)]
+    #[case("mod_loaded('B') or mod_loaded('A')", true)]
+    #[case("not (mod_loaded('A') and mod_loaded('B'))", true)]
+    fn test_evaluate(#[case] input: &str, #[case] expected: bool) {
+        assert_eq!(expected, Condition::parse(input).unwrap().evaluate(&["A"]));
+    }
+}
@@ -0,0 +1,223 @@
+use super::{command::Command, conditional::BranchKind, CsvInstruction, InstructionSet};
+use crate::xpath::{Axis, LocationPath, NodeTest};
+use quick_xml::events::{BytesStart, Event};
+
+impl Command {
+    /// Describes the command in plain language, e.g. "Sets Stacknumber of item gunPistol to 500".
+    /// A conditional is described by a line per branch, each followed by its commands (indented).
+    /// Comments and other non-patch commands are not described.
+    pub fn explain(&self) -> Vec<String> {
+        if let Command::Conditional(branches) = self {
+            let mut lines = Vec::new();
+            for branch in branches {
+                let condition = branch.condition.as_ref().map(ToString::to_string).unwrap_or_default();
+                lines.push(match branch.kind {
+                    BranchKind::If => format!("If {condition}:"),
+                    BranchKind::ElseIf => format!("Otherwise, if {condition}:"),
+                    BranchKind::Else => String::from("Otherwise:"),
+                });
+                for command in &branch.commands {
+                    lines.extend(command.explain().into_iter().map(|line| format!("  {line}")));
+                }
+            }
+            return lines;
+        }
+
+        let Some(is) = self.instructions() else {
+            return Vec::new();
+        };
+        let target = Target::new(is);
+        let line = match self {
+            Command::Append(_) if target.attribute.is_some() => {
+                format!("Appends \"{}\" to {}", is.text(), target.attribute_of())
+            }
+            Command::Append(_) => format!("Adds {} to {}", children(is), target.subject),
+            Command::Csv(_) => {
+                let values = is.csv_values().join(", ");
+                match is.csv_op {
+                    Some(CsvInstruction::Remove(_)) => format!("Removes {values} from {}", target.attribute_of()),
+                    _ => format!("Adds {values} to {}", target.attribute_of()),
+                }
+            }
+            Command::InsertAfter(_) => format!("Inserts {} after {}", children(is), target.subject),
+            Command::InsertBefore(_) => format!("Inserts {} before {}", children(is), target.subject),
+            Command::Remove(_) | Command::RemoveAttribute(_) if target.attribute.is_some() => {
+                format!("Removes {}", target.attribute_of())
+            }
+            Command::Remove(_) | Command::RemoveAttribute(_) => format!("Removes {}", target.subject),
+            Command::Set(_) if target.attribute.is_some() => {
+                format!("Sets {} to {}", target.attribute_of(), is.text())
+            }
+            Command::Set(_) => format!("Sets the text of {} to {}", target.subject, is.text()),
+            Command::SetAttribute(_) => {
+                let name = String::from_utf8_lossy(is.attribute.as_deref().unwrap_or_default());
+                format!("Sets {name} of {} to {}", target.subject, is.text())
+            }
+            _ => return Vec::new(),
+        };
+
+        vec![line]
+    }
+}
+
+/// What a command's xpath refers to, in plain language
+struct Target {
+    /// The attribute targeted, or the name of the `property` whose `value` is targeted
+    attribute: Option<String>,
+    /// The element(s) targeted, e.g. `item gunPistol`
+    subject: String,
+}
+
+impl Target {
+    fn new(is: &InstructionSet) -> Self {
+        let xpath = String::from_utf8_lossy(&is.xpath).into_owned();
+        let Ok(path) = is.location_path() else {
+            return Self {
+                attribute: None,
+                subject: format!("`{xpath}`"),
+            };
+        };
+
+        let mut named = path.named_elements();
+        let mut attribute = path.target_attribute().map(str::to_string);
+        // `property[@name='X']/@value` is the value of property X
+        if attribute.as_deref() == Some("value") && last_element(&path) == Some("property") {
+            if let [.., _, ("property", name)] = named[..] {
+                attribute = Some(name.to_string());
+                named.pop();
+            }
+        }
+
+        let subject = if !named.is_empty() {
+            named
+                .iter()
+                .rev()
+                .map(|&(element, name)| match element {
+                    "*" => name.to_string(),
+                    element => format!("{element} {name}"),
+                })
+                .collect::<Vec<_>>()
+                .join(" of ")
+        } else {
+            // A plain path such as `/items` or `/items/item`
+            let plain = path.absolute
+                && path.steps.iter().all(|step| {
+                    step.predicates.is_empty()
+                        && matches!(step.axis, Axis::Attribute | Axis::Child)
+                        && matches!(step.test, NodeTest::Name(_))
+                });
+            let elements = path.steps.iter().filter(|step| step.axis == Axis::Child).count();
+            match last_element(&path) {
+                Some(element) if plain && elements == 1 => element.to_string(),
+                Some(element) if plain => format!("every {element}"),
+                _ => {
+                    // The xpath already names the attribute
+                    attribute = None;
+                    format!("`{xpath}`")
+                }
+            }
+        };
+
+        Self { attribute, subject }
+    }
+
+    /// e.g. `Stacknumber of item gunPistol`
+    fn attribute_of(&self) -> String {
+        match &self.attribute {
+            Some(attribute) => format!("{attribute} of {}", self.subject),
+            None => self.subject.clone(),
+        }
+    }
+}
+
+/// The name of the element selected by the last element step of a path
+fn last_element(path: &LocationPath) -> Option<&str> {
+    path.steps
+        .iter()
+        .rev()
+        .find(|step| step.axis == Axis::Child)
+        .and_then(|step| match &step.test {
+            NodeTest::Name(name) => Some(name.as_str()),
+            _ => None,
+        })
+}
+
+/// Describes the top-level elements of an instruction set, e.g. `item myNewGun, item myNewAmmo`
+fn children(is: &InstructionSet) -> String {
+    let mut depth = 0;
+    let mut elements = Vec::new();
+    for event in &is.values {
+        match event {
+            Event::Start(start) => {
+                if depth == 0 {
+                    elements.push(describe(start));
+                }
+                depth += 1;
+            }
+            Event::Empty(start) if depth == 0 => elements.push(describe(start)),
+            Event::End(_) => depth -= 1,
+            _ => (),
+        }
+    }
+
+    match elements.len() {
+        0 => String::from("nothing"),
+        1..=3 => elements.join(", "),
+        count => format!("{count} elements"),
+    }
+}
+
+fn describe(start: &BytesStart) -> String {
+    let tag = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    match start.try_get_attribute("name").ok().flatten() {
+        Some(name) => format!("{tag} {}", name.unescape_value().unwrap_or_default()),
+        None => tag,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(tag: &str, xpath: &str) -> Command {
+        Command::parse(tag).set(InstructionSet::builder(xpath).build().unwrap())
+    }
+
+    fn with(tag: &str, builder: crate::modlet::InstructionSetBuilder) -> Command {
+        Command::parse(tag).set(builder.build().unwrap())
+    }
+
+    #[rstest]
+    #[case::set_property(
+        with("set", InstructionSet::builder("/items/item[@name='gunPistol']/property[@name='Stacknumber']/@value").text("500")),
+        "Sets Stacknumber of item gunPistol to 500"
+    )]
+    #[case::set_attribute(
+        with("setattribute", InstructionSet::builder("/blocks/block[@name='cntChest']").attribute("Extends").text("cntBox")),
+        "Sets Extends of block cntChest to cntBox"
+    )]
+    #[case::remove(
+        command("remove", "/recipes/recipe[@name='ammo9mmBullet']"),
+        "Removes recipe ammo9mmBullet"
+    )]
+    #[case::remove_attribute(
+        command("removeattribute", "/items/item[@name='gunPistol']/@Tags"),
+        "Removes Tags of item gunPistol"
+    )]
+    #[case::append(
+        with("append", InstructionSet::builder("/items").xml("<item name=\"myNewGun\"><property name=\"a\"/></item>")),
+        "Adds item myNewGun to items"
+    )]
+    #[case::csv(
+        with("csv", InstructionSet::builder("//item[@name='gunPistol']/property[@name='Tags']/@value").csv(CsvInstruction::Add(','), ["melee", "perkGunslinger"])),
+        "Adds melee, perkGunslinger to Tags of item gunPistol"
+    )]
+    #[case::unnamed(
+        command("remove", "/items/item[starts-with(@name, 'ammo')]"),
+        "Removes `/items/item[starts-with(@name, 'ammo')]`"
+    )]
+    #[case::unnamed_attribute(command("removeattribute", "//item/@Tags"), "Removes `//item/@Tags`")]
+    fn test_explain(#[case] command: Command, #[case] expected: &str) {
+        assert_eq!(vec![expected], command.explain());
+    }
+}
@@ -1,197 +1,695 @@
 /// This module contains the implementation of the `ModletXML` struct and related types.
 /// The `ModletXML` struct represents an XML file containing modlet instructions.
 /// It provides methods for loading the XML file and extracting the commands from it.
-use eyre::eyre;
-use quick_xml::{events::Event, reader::Reader};
+use crate::error::{ModletError, ParseErrorKind};
+use quick_xml::{
+    events::{BytesDecl, BytesStart, Event},
+    reader::Reader,
+};
 use std::{
     borrow::Cow,
-    collections::VecDeque,
+    fs,
     io::Write,
     path::{Path, PathBuf},
     str::{self},
+    sync::Arc,
 };
 
+mod builder;
 mod command;
-use command::{Command, CsvInstruction, InstructionSet};
+mod conditional;
+mod explain;
+#[cfg(feature = "patch-files")]
+mod patch_file;
+#[cfg(feature = "serde")]
+mod serialize;
+mod span;
+pub use builder::InstructionSetBuilder;
+pub use command::{Command, CsvInstruction, InstructionSet};
+pub use conditional::{BranchKind, Condition, ConditionalBranch};
+pub use span::{Snippet, Span};
+
+/// Extensions of the structured (non-XML) patch files which are compiled into commands
+pub const PATCH_FILE_EXTENSIONS: [&str; 3] = ["toml", "yaml", "yml"];
+
+/// Returns the lowercased extension of a path
+fn extension(path: &Path) -> String {
+    path.extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_ascii_lowercase()
+}
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModletXML {
     pub commands: Vec<Command>,
+    /// Markup found after the root element is closed (comments, processing instructions)
+    #[cfg_attr(feature = "serde", serde(with = "serialize::events"))]
+    pub epilog: Vec<Event<'static>>,
     pub path: PathBuf,
+    /// Markup found before the root element (the `<?xml?>` declaration, DocType, processing instructions)
+    #[cfg_attr(feature = "serde", serde(with = "serialize::events"))]
+    pub prolog: Vec<Event<'static>>,
+    /// The root element (usually `<config>`), including its attributes
+    #[cfg_attr(feature = "serde", serde(with = "serialize::option_start"))]
+    pub root: Option<BytesStart<'static>>,
 }
 
 impl ModletXML {
-    pub fn load(mut self) -> eyre::Result<Self> {
+    pub fn load(self) -> Result<Self, ModletError> {
         if !self.path.exists() {
-            return Err(eyre!("Modlet XML {}: file not found", self.path.display()));
+            return Err(ModletError::NotFound(self.path));
         }
-        self.commands = load_xml(self.path.as_ref())?;
 
-        Ok(self)
+        let source = fs::read_to_string(&self.path)?;
+
+        self.read(&source)
     }
 
     pub fn new(path: impl AsRef<Path>) -> Self {
         Self {
-            path: path.as_ref().to_path_buf(),
             commands: Vec::new(),
+            epilog: Vec::new(),
+            path: path.as_ref().to_path_buf(),
+            prolog: Vec::new(),
+            root: None,
         }
     }
 
+    /// Creates an empty document with an `<?xml?>` declaration and the given root element (usually `config`)
+    pub fn with_root(path: impl AsRef<Path>, root: &str) -> Self {
+        Self {
+            commands: vec![Command::StartTag(Some(root.to_string()))],
+            prolog: vec![Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None))],
+            root: Some(BytesStart::new(root.to_string())),
+            ..Self::new(path)
+        }
+    }
+
+    /// Structured patch files can only be compiled with the `patch-files` feature
+    ///
+    /// # Errors
+    ///
+    /// * Always, naming the file
+    #[cfg(not(feature = "patch-files"))]
+    pub fn compile(self) -> Result<Self, ModletError> {
+        Err(ModletError::InvalidPatchFile {
+            path: self.path,
+            message: "compiling patch files requires the `patch-files` feature".to_string(),
+        })
+    }
+
+    pub(crate) fn read(mut self, source: &str) -> Result<Self, ModletError> {
+        let mut parser = XmlParser::new(self.path.as_path().into(), source);
+
+        self.commands = parser.read_commands(None)?;
+        self.epilog = parser.epilog;
+        self.prolog = parser.prolog;
+        self.root = parser.root;
+
+        Ok(self)
+    }
+
+    /// The path of the config file this patches, relative to `Config/`.
+    /// Structured patch files (e.g. `items.yaml`) patch the XML file of the same name.
     pub fn filename(&self) -> Cow<Path> {
-        self.path
+        let mut filename = self
+            .path
             .iter()
             .skip_while(|&ancestor| ancestor.to_ascii_lowercase() != "config")
             .skip(1)
-            .collect::<PathBuf>()
-            .into()
+            .collect::<PathBuf>();
+
+        if PATCH_FILE_EXTENSIONS.contains(&extension(&filename).as_str()) {
+            filename.set_extension("xml");
+        }
+
+        filename.into()
     }
 
-    pub fn write(&self, writer: &mut quick_xml::Writer<impl Write>) -> eyre::Result<()> {
+    /// Writes the complete document, including the prolog and root element, as it was loaded
+    pub fn write(&self, writer: &mut quick_xml::Writer<impl Write>) -> Result<(), ModletError> {
+        self.prolog.iter().try_for_each(|event| writer.write_event(event))?;
+
+        for command in &self.commands {
+            match (command, &self.root) {
+                (Command::StartTag(_), Some(root)) => writer.write_event(Event::Start(root.borrow()))?,
+                _ => command.write(writer)?,
+            }
+        }
+
+        if let Some(root) = &self.root {
+            writer.write_event(Event::End(root.to_end()))?;
+        }
+        self.epilog.iter().try_for_each(|event| writer.write_event(event))?;
+
+        Ok(())
+    }
+
+    /// Writes the complete document to its path, creating any missing directories
+    pub fn save(&self) -> Result<(), ModletError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&self.path, self.format()?)?;
+
+        Ok(())
+    }
+
+    /// Writes the complete document in the canonical format: one command (or comment) per line, indented by four
+    /// spaces per level, with double-quoted attributes, canonical command names and a trailing newline
+    pub fn format(&self) -> Result<String, ModletError> {
+        let mut writer = quick_xml::Writer::new_with_indent(Vec::new(), b' ', 4);
+        self.write(&mut writer)?;
+
+        let mut formatted = String::from_utf8_lossy(&writer.into_inner()).into_owned();
+        formatted.push('\n');
+
+        Ok(formatted)
+    }
+
+    /// Writes only the commands, as they would appear within a packaged `<bundle>`
+    pub fn write_commands(&self, writer: &mut quick_xml::Writer<impl Write>) -> Result<(), ModletError> {
         self.commands.iter().try_for_each(|command| command.write(writer))?;
 
         Ok(())
     }
 }
 
-fn load_xml(path: &Path) -> eyre::Result<Vec<Command>> {
-    let mut commands = Vec::new();
-    let mut reader = Reader::from_file(path)?;
-    let mut stack = VecDeque::<Command>::new();
-    // The modlet we're building
-    let mut instruction = InstructionSet::new();
-    let mut start_tag = String::new();
-    let mut buf = Vec::new();
+/// Reads modlet commands from an XML source, keeping track of where errors occur
+struct XmlParser<'a> {
+    buf: Vec<u8>,
+    epilog: Vec<Event<'static>>,
+    path: Arc<Path>,
+    prolog: Vec<Event<'static>>,
+    reader: Reader<&'a [u8]>,
+    root: Option<BytesStart<'static>>,
+    source: &'a str,
+    /// Byte offset at which the most recently read event started
+    start: usize,
+}
 
-    // Set options on Reader
-    reader.trim_text(true);
-    reader.trim_markup_names_in_closing_tags(true);
+impl<'a> XmlParser<'a> {
+    fn new(path: Arc<Path>, source: &'a str) -> Self {
+        let mut reader = Reader::from_str(source);
 
-    loop {
-        let last_command = stack.get(0).unwrap_or(&Command::NoOp).as_ref();
+        // Set options on Reader
+        reader.trim_text(true);
+        reader.trim_markup_names_in_closing_tags(true);
 
-        match reader.read_event_into(&mut buf) {
-            Err(event) => panic!("Error at position {}: {:?}", reader.buffer_position(), event),
+        Self {
+            buf: Vec::new(),
+            epilog: Vec::new(),
+            path,
+            prolog: Vec::new(),
+            reader,
+            root: None,
+            source,
+            start: 0,
+        }
+    }
 
-            // Found a comment
-            Ok(Event::Comment(event)) => {
-                // let event = event.into_owned();
-                let comment = event.unescape().unwrap_or_default().to_string();
+    /// The byte offset of the most recently read event, skipping any whitespace trimmed by the reader
+    fn offset(&self) -> usize {
+        self.start
+            + self.source[self.start..]
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or_default()
+    }
 
-                if !comment.is_empty() {
-                    commands.push(Command::Comment(Cow::Owned(comment)));
-                }
+    /// Builds a span from `start` to the current reader position
+    fn span(&self, start: usize) -> Span {
+        let (line, column) = line_column(self.source, start);
+
+        Span {
+            column,
+            line,
+            path: self.path.clone(),
+            range: start..self.reader.buffer_position(),
+        }
+    }
+
+    /// Builds an error located at the start of the most recently read event
+    fn error(&self, kind: ParseErrorKind) -> ModletError {
+        let (line, column) = line_column(self.source, self.offset());
+
+        ModletError::Parse {
+            path: self.path.to_path_buf(),
+            line,
+            column,
+            kind,
+        }
+    }
+
+    fn read_event(&mut self, tag: &str) -> Result<Event<'static>, ModletError> {
+        self.buf.clear();
+        self.start = self.reader.buffer_position();
+
+        match self.reader.read_event_into(&mut self.buf) {
+            Ok(event) => Ok(event.into_owned()),
+            Err(quick_xml::Error::NonDecodable(_)) => {
+                Err(self.error(ParseErrorKind::InvalidUtf8 { tag: tag.to_string() }))
             }
+            Err(err) => Err(self.error(ParseErrorKind::Reader(err))),
+        }
+    }
+
+    fn tag_name(&self, event: &BytesStart, tag: &str) -> Result<String, ModletError> {
+        str::from_utf8(event.name().as_ref())
+            .map(str::to_string)
+            .map_err(|_| self.error(ParseErrorKind::InvalidUtf8 { tag: tag.to_string() }))
+    }
 
-            // Found a start tag
-            Ok(Event::Start(event)) => {
-                let event = event.into_owned();
-                let tag_name = event.name();
-                let tag_name = str::from_utf8(tag_name.as_ref())?;
-                let mut command = Command::parse(tag_name);
+    /// Reads commands until the closing `end_tag` is found (or the end of the file, if `None`)
+    fn read_commands(&mut self, end_tag: Option<&str>) -> Result<Vec<Command>, ModletError> {
+        let mut commands = Vec::new();
+        let parent = end_tag.unwrap_or_default();
 
-                if start_tag.is_empty() && command.as_ref() == "unknown" && last_command == "noop" {
-                    start_tag = tag_name.to_string();
-                    command = Command::StartTag(Some(tag_name.to_string()));
+        loop {
+            match self.read_event(parent)? {
+                // Found a comment
+                Event::Comment(event) if end_tag.is_none() && self.root.is_some() => {
+                    self.epilog.push(Event::Comment(event));
                 }
+                Event::Comment(event) => {
+                    // Comments are not escaped, so they are kept exactly as written
+                    let comment = String::from_utf8_lossy(&event).into_owned();
 
-                if command::COLLECTION_COMMANDS.contains(&last_command) {
-                    instruction.values.push(Event::Start(event));
-                } else if command.as_ref() != "unknown" && command.as_ref() != "noop" {
-                    // println!("[STARTING] tag {:?} ({command})", str::from_utf8(e.name().as_ref()).unwrap());
+                    if !comment.is_empty() {
+                        commands.push(Command::Comment(Cow::Owned(comment), Some(self.span(self.offset()))));
+                    }
+                }
 
-                    // We don't want to add the start_tag command to the stack
-                    if command.as_ref() == "starttag" {
-                        continue;
+                // Found markup outside of the root element
+                event @ (Event::Decl(_) | Event::DocType(_) | Event::PI(_)) if end_tag.is_none() => match self.root {
+                    Some(_) => self.epilog.push(event),
+                    None => self.prolog.push(event),
+                },
+
+                // Found a start tag
+                Event::Start(event) => {
+                    let start = self.offset();
+                    let tag_name = self.tag_name(&event, parent)?;
+                    let command = Command::parse(&tag_name);
+
+                    match command.as_ref() {
+                        // The root tag (usually `<config>`) contains the actual commands
+                        "unknown" if end_tag.is_none() && self.root.is_none() => {
+                            self.root = Some(event.clone());
+                            commands.push(Command::StartTag(Some(tag_name.clone())));
+                            commands.extend(self.read_commands(Some(&tag_name))?);
+                        }
+                        "unknown" => {
+                            // Unknown elements are kept as they were read, so that they aren't lost when written
+                            let mut events = vec![Event::Start(event.to_owned())];
+                            events.extend(self.read_values(&tag_name)?);
+                            events.push(Event::End(event.to_end().into_owned()));
+
+                            commands.push(Command::Unknown(tag_name.into(), events, Some(self.span(start))));
+                        }
+                        "conditional" => commands.push(self.read_conditional()?),
+                        name if command::COLLECTION_COMMANDS.contains(&name)
+                            || command::TEXT_COMMANDS.contains(&name)
+                            || command::EMPTY_COMMANDS.contains(&name) =>
+                        {
+                            let mut instruction = self.instruction_set(&event, &tag_name)?;
+                            instruction.values = self.read_values(&tag_name)?;
+                            instruction.span = Some(self.span(start));
+
+                            commands.push(command.set(instruction));
+                        }
+                        _ => return Err(self.error(ParseErrorKind::UnhandledStartTag { tag: tag_name })),
                     }
+                }
 
-                    let my_char = str::from_utf8(get_attribute(&event, "delim").unwrap_or(vec![b',']).as_ref())
-                        .unwrap()
-                        .to_string();
-                    let delim: char = my_char.chars().next().unwrap();
-
-                    instruction.xpath = get_attribute(&event, "xpath").unwrap();
-                    instruction.csv_op = match get_attribute(&event, "op") {
-                        Some(op) => match str::from_utf8(&op).unwrap() {
-                            "add" => Some(CsvInstruction::Add(delim)),
-                            "remove" => Some(CsvInstruction::Remove(delim)),
-                            _ => None,
-                        },
-                        None => None,
-                    };
-                    stack.push_back(command);
+                // This is an empty tag (likely remove or remove_attribute)
+                Event::Empty(event) => {
+                    let tag_name = self.tag_name(&event, parent)?;
+                    let command = Command::parse(&tag_name);
+
+                    if command::EMPTY_COMMANDS.contains(&command.as_ref()) {
+                        let mut instruction = self.instruction_set(&event, &tag_name)?;
+                        instruction.span = Some(self.span(self.offset()));
+
+                        commands.push(command.set(instruction));
+                    } else if end_tag.is_none() && self.root.is_none() && command.as_ref() == "unknown" {
+                        // An empty root element (a file without any commands)
+                        self.root = Some(event);
+                        commands.push(Command::StartTag(Some(tag_name)));
+                    } else if command.as_ref() == "unknown" {
+                        let span = self.span(self.offset());
+                        commands.push(Command::Unknown(tag_name.into(), vec![Event::Empty(event)], Some(span)));
+                    } else {
+                        return Err(self.error(ParseErrorKind::UnhandledEmptyTag { tag: tag_name }));
+                    }
                 }
-            }
 
-            // This is an empty tag (likely remove or remove_attribute)
-            Ok(Event::Empty(event)) => {
-                let event = event.into_owned();
-                let tag_name = event.name();
-                let tag_name = str::from_utf8(tag_name.as_ref())?;
-                let value = str::from_utf8(event.as_ref())?;
-
-                if command::EMPTY_COMMANDS.contains(&tag_name) || command::COLLECTION_COMMANDS.contains(&last_command) {
-                    instruction.values.push(Event::Empty(event));
-                } else {
-                    panic!("Unhandled empty tag received: {value}");
+                // Found text outside of a command
+                Event::Text(event) => {
+                    return Err(self.error(ParseErrorKind::UnhandledText {
+                        tag: parent.to_string(),
+                        text: String::from_utf8_lossy(&event).into_owned(),
+                    }));
+                }
+
+                // Found the end of the enclosing tag
+                Event::End(_) => break,
+
+                // exits the loop when reaching end of file
+                Event::Eof => match end_tag {
+                    Some(end_tag) => {
+                        return Err(self.error(ParseErrorKind::UnexpectedEof {
+                            tag: end_tag.to_string(),
+                        }))
+                    }
+                    None => break,
+                },
+
+                // Something unexpected happened
+                event => {
+                    return Err(self.error(ParseErrorKind::UnhandledEvent {
+                        tag: parent.to_string(),
+                        event: String::from_utf8_lossy(event.as_ref()).into_owned(),
+                    }))
                 }
             }
+        }
 
-            // Found text between tags, add it to our struct's value.
-            Ok(Event::Text(event)) => {
-                let event = event.into_owned();
-                let value = str::from_utf8(&event)?;
-                let value = value.to_string();
+        Ok(commands)
+    }
 
-                if command::TEXT_COMMANDS.contains(&last_command) {
-                    instruction.values.push(Event::Text(event));
-                } else {
-                    panic!("Unhandled text tag received: {value} for {last_command}");
+    /// Reads the raw events within a command, up to (and excluding) its closing tag
+    fn read_values(&mut self, end_tag: &str) -> Result<Vec<Event<'static>>, ModletError> {
+        let mut values = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            let event = self.read_event(end_tag)?;
+
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) if depth == 0 => break,
+                Event::End(_) => depth -= 1,
+                Event::Eof => {
+                    return Err(self.error(ParseErrorKind::UnexpectedEof {
+                        tag: end_tag.to_string(),
+                    }))
                 }
+                _ => (),
             }
 
-            // Found an end tag
-            Ok(Event::End(event)) => {
-                let event = event.into_owned();
-                let tag_name = str::from_utf8(event.as_ref())?;
-                let mut command = Command::parse(tag_name);
+            values.push(event);
+        }
+
+        Ok(values)
+    }
 
-                if command.as_ref() == "unknown" && !start_tag.is_empty() {
-                    command = Command::StartTag(Some(start_tag.to_string()));
+    /// Reads the `<if>`, `<elseif>` and `<else>` branches of a `<conditional>` block
+    fn read_conditional(&mut self) -> Result<Command, ModletError> {
+        const TAG: &str = "conditional";
+        let mut branches = Vec::new();
+
+        loop {
+            let (event, is_empty) = match self.read_event(TAG)? {
+                Event::Start(event) => (event, false),
+                Event::Empty(event) => (event, true),
+                Event::Comment(_) => continue,
+                Event::End(_) => break,
+                Event::Eof => return Err(self.error(ParseErrorKind::UnexpectedEof { tag: TAG.to_string() })),
+                event => {
+                    return Err(self.error(ParseErrorKind::UnhandledEvent {
+                        tag: TAG.to_string(),
+                        event: String::from_utf8_lossy(event.as_ref()).into_owned(),
+                    }))
                 }
+            };
+
+            let start = self.offset();
+            let tag_name = self.tag_name(&event, TAG)?;
+            let Some(kind) = BranchKind::parse(&tag_name) else {
+                return Err(self.error(ParseErrorKind::UnhandledStartTag { tag: tag_name }));
+            };
+            let condition = match self.get_attribute(&event, &tag_name, "cond")? {
+                Some(cond) => Some(
+                    str::from_utf8(&cond)
+                        .map_err(|err| err.to_string())
+                        .and_then(|cond| Condition::parse(cond).map_err(|err| err.to_string()))
+                        .map_err(|message| {
+                            self.error(ParseErrorKind::InvalidCondition {
+                                tag: tag_name.clone(),
+                                message,
+                            })
+                        })?,
+                ),
+                None => None,
+            };
+            let commands = if is_empty {
+                Vec::new()
+            } else {
+                self.read_commands(Some(&tag_name))?
+            };
+
+            branches.push(ConditionalBranch {
+                commands,
+                condition,
+                kind,
+                span: Some(self.span(start)),
+            });
+        }
 
-                if command::COLLECTION_COMMANDS.contains(&last_command) && command.as_ref() != last_command {
-                    instruction.values.push(Event::End(event));
-                } else {
-                    // println!("[ENDING] tag {tag} ({command}) / {last_command}");
+        Ok(Command::Conditional(branches))
+    }
 
-                    commands.push(command.set(instruction));
-                    stack.clear();
-                    instruction = InstructionSet::new();
+    /// Builds an instruction set from a command's attributes
+    fn instruction_set(&self, event: &BytesStart, tag: &str) -> Result<InstructionSet, ModletError> {
+        let invalid = |message: String| {
+            self.error(ParseErrorKind::InvalidAttribute {
+                tag: tag.to_string(),
+                message,
+            })
+        };
+        let delim = match self.get_attribute(event, tag, "delim")? {
+            Some(delim) => {
+                let delim = String::from_utf8_lossy(&delim).into_owned();
+                let mut chars = delim.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if !c.is_whitespace() => c,
+                    _ => return Err(invalid(format!("delim must be a single character, found \"{delim}\""))),
                 }
             }
+            None => ',',
+        };
+        let csv_op = match self.get_attribute(event, tag, "op")?.as_deref() {
+            Some(b"add") => Some(CsvInstruction::Add(delim)),
+            Some(b"remove") => Some(CsvInstruction::Remove(delim)),
+            Some(op) if tag == "csv" => {
+                let op = String::from_utf8_lossy(op);
+                return Err(invalid(format!("op must be \"add\" or \"remove\", found \"{op}\"")));
+            }
+            _ => None,
+        };
+
+        Ok(InstructionSet {
+            attribute: self.get_attribute(event, tag, "name")?,
+            csv_op,
+            span: None,
+            values: Vec::new(),
+            xpath: self.get_attribute(event, tag, "xpath")?.unwrap_or_default(),
+        })
+    }
 
-            // exits the loop when reaching end of file
-            Ok(Event::Eof) => break,
-
-            // Something unexpected happened. Panic and exit.
-            Ok(event) => {
-                panic!("[UNKNOWN] event: {:?}", event.as_ref());
+    fn get_attribute(&self, e: &BytesStart, tag: &str, attr: &str) -> Result<Option<Vec<u8>>, ModletError> {
+        let invalid = |message: String| {
+            self.error(ParseErrorKind::InvalidAttribute {
+                tag: tag.to_string(),
+                message,
+            })
+        };
+
+        for attribute in e.attributes() {
+            let attribute = attribute.map_err(|err| invalid(err.to_string()))?;
+            if str::from_utf8(attribute.key.as_ref()) == Ok(attr) {
+                let value = attribute.unescape_value().map_err(|err| invalid(err.to_string()))?;
+                return Ok(Some(value.as_bytes().to_owned()));
             }
         }
 
-        buf.clear();
+        Ok(None)
     }
+}
 
-    Ok(commands)
+/// Converts a byte offset into a (1-based) line and column
+pub(crate) fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().count() + 1;
+
+    (line, column)
 }
 
-fn get_attribute(e: &quick_xml::events::BytesStart, attr: &str) -> Option<Vec<u8>> {
-    for attribute in e.attributes() {
-        let attribute = attribute.unwrap();
-        if str::from_utf8(attribute.key.as_ref()) == Ok(attr) {
-            return Some(attribute.unescape_value().unwrap_or_default().as_bytes().to_owned());
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<Command>, ModletError> {
+        XmlParser::new(Path::new("Config/items.xml").into(), source).read_commands(None)
+    }
+
+    #[rstest]
+    #[case::empty_command("<config>\n    <append xpath=\"/items\"/>\n</config>", 2, 5, "append")]
+    #[case::text("<config>\n  stray text\n</config>", 2, 3, "config")]
+    #[case::unclosed("<config>\n<set xpath=\"/items\">1", 2, 21, "set")]
+    #[case::bad_condition(
+        "<config>\n<conditional>\n  <if cond=\"mod_loaded('A'\"/>\n</conditional>\n</config>",
+        3,
+        3,
+        "if"
+    )]
+    fn test_parse_error_location(#[case] source: &str, #[case] line: usize, #[case] column: usize, #[case] tag: &str) {
+        let err = parse(source).unwrap_err();
+
+        assert!(
+            matches!(err, ModletError::Parse { line: l, column: c, .. } if l == line && c == column),
+            "{err}"
+        );
+        assert_eq!(Some(tag), err.tag());
     }
 
-    None
+    #[rstest]
+    #[case::empty("", "delim must be a single character, found \"\"")]
+    #[case::multiple("||", "delim must be a single character, found \"||\"")]
+    #[case::whitespace(" ", "delim must be a single character, found \" \"")]
+    fn test_invalid_delim(#[case] delim: &str, #[case] expected: &str) {
+        let source = format!("<config>\n<csv xpath=\"/a/@b\" delim=\"{delim}\" op=\"add\">x</csv>\n</config>");
+
+        assert_eq!(
+            format!("Config/items.xml:2:1: Invalid attribute in <csv>: {expected}"),
+            parse(&source).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_error_message() {
+        let err = parse("<config>\n    <append xpath=\"/items\"/>\n</config>").unwrap_err();
+
+        assert_eq!("Config/items.xml:2:5: Unhandled empty tag <append/>", err.to_string());
+    }
+
+    #[rstest]
+    #[case::empty("<bogus xpath=\"/a\"/>")]
+    #[case::start_end("<bogus xpath=\"/a\"><b/></bogus>")]
+    fn test_unknown_tag(#[case] tag: &str) {
+        let source =
+            format!("<config>\n    <set xpath=\"/a/@b\">1</set>\n    {tag}\n    <remove xpath=\"/c\"/>\n</config>");
+        let commands = parse(&source).unwrap();
+
+        let Command::Unknown(name, _, Some(span)) = &commands[2] else {
+            panic!("expected an unknown tag, found {:?}", commands[2]);
+        };
+        assert_eq!("bogus", name);
+        assert_eq!((3, 5, tag), (span.line, span.column, &source[span.range.clone()]));
+        assert!(matches!(commands[3], Command::Remove(_)));
+    }
+
+    #[test]
+    fn test_spans() {
+        let source = "<config>\n    <set xpath=\"/a/@b\">1</set>\n    <conditional>\n        <if cond=\"mod_loaded('A')\">\n            <remove xpath=\"/c\"/>\n        </if>\n    </conditional>\n</config>";
+        let commands = parse(source).unwrap();
+        let span = |command: &Command| {
+            let span = command.span().unwrap().clone();
+            (span.line, span.column, &source[span.range])
+        };
+
+        assert_eq!((2, 5, "<set xpath=\"/a/@b\">1</set>"), span(&commands[1]));
+        assert_eq!(
+            (
+                4,
+                9,
+                "<if cond=\"mod_loaded('A')\">\n            <remove xpath=\"/c\"/>\n        </if>"
+            ),
+            span(&commands[2])
+        );
+
+        let Command::Conditional(branches) = &commands[2] else {
+            panic!("expected a conditional");
+        };
+        assert_eq!((5, 13, "<remove xpath=\"/c\"/>"), span(&branches[0].commands[0]));
+    }
+
+    fn write(xml: &ModletXML) -> String {
+        let mut writer = quick_xml::Writer::new_with_indent(Vec::new(), b' ', 4);
+        xml.write(&mut writer).unwrap();
+
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[rstest]
+    #[case::declaration(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<config>\n    <set xpath=\"/a/@b\">1</set>\n</config>"
+    )]
+    #[case::doctype_and_pi(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE config>\n<?pi data?>\n<config>\n    <remove xpath=\"/a\"/>\n</config>"
+    )]
+    #[case::root_attributes("<configs version=\"2\">\n    <remove xpath=\"/a\"/>\n</configs>")]
+    #[case::cdata("<config>\n    <set xpath=\"/a/@b\"><![CDATA[<b> & c]]></set>\n</config>")]
+    #[case::entities("<config>\n    <setattribute xpath=\"/a[@n=&quot;x&quot;]\" name=\"b\">a &amp; b &lt; c</setattribute>\n</config>")]
+    #[case::nested_comments(
+        "<config>\n    <append xpath=\"/a\">\n        <!-- a & b -->\n        <b/>\n    </append>\n</config>"
+    )]
+    #[case::unknown_elements(
+        "<config>\n    <bogus a=\"1\"/>\n    <unknown xpath=\"/a\">\n        <b c=\"&amp;\">text</b>\n    </unknown>\n    <conditional>\n        <if cond=\"mod_loaded('A')\">\n            <bogus/>\n        </if>\n    </conditional>\n</config>"
+    )]
+    #[case::csv("<config>\n    <csv xpath=\"/a/@b\" delim=\";\" op=\"add\">x;y</csv>\n</config>\n<!-- trailing -->")]
+    fn test_round_trip(#[case] source: &str) {
+        let path = Path::new("Config/items.xml");
+        let xml = ModletXML::new(path).read(source).unwrap();
+        let written = write(&xml);
+
+        assert_eq!(source, written);
+        assert_eq!(written, write(&ModletXML::new(path).read(&written).unwrap()));
+    }
+
+    #[rstest]
+    #[case::indentation(
+        "<config>\n<remove xpath=\"/a\"/>\n      <set xpath=\"/a/@b\">\n  1\n</set></config>",
+        "<config>\n    <remove xpath=\"/a\"/>\n    <set xpath=\"/a/@b\">1</set>\n</config>\n"
+    )]
+    #[case::quoting(
+        "<config><remove xpath='/a[@n=\"x\"]'></remove></config>",
+        "<config>\n    <remove xpath=\"/a[@n=&quot;x&quot;]\"/>\n</config>\n"
+    )]
+    #[case::casing(
+        "<config><INSERTAFTER xpath=\"/a\"><b/></INSERTAFTER><setAttribute xpath=\"/a\" name=\"c\">1</setAttribute></config>",
+        "<config>\n    <insertAfter xpath=\"/a\">\n        <b/>\n    </insertAfter>\n    <setattribute xpath=\"/a\" name=\"c\">1</setattribute>\n</config>\n"
+    )]
+    #[case::comments(
+        "<config><!-- a --><remove xpath=\"/a\"/> <!-- b --><conditional><if cond=\"mod_loaded('A')\"><!-- c --></if><else></else></conditional></config>",
+        "<config>\n    <!-- a -->\n    <remove xpath=\"/a\"/>\n    <!-- b -->\n    <conditional>\n        <if cond=\"mod_loaded('A')\">\n            <!-- c -->\n        </if>\n        <else/>\n    </conditional>\n</config>\n"
+    )]
+    fn test_format(#[case] source: &str, #[case] expected: &str) {
+        let xml = ModletXML::new("Config/items.xml").read(source).unwrap();
+        let formatted = xml.format().unwrap();
+
+        assert_eq!(expected, formatted);
+        assert_eq!(
+            formatted,
+            ModletXML::new("Config/items.xml")
+                .read(&formatted)
+                .unwrap()
+                .format()
+                .unwrap()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let source = "<?xml version=\"1.0\"?>\n<config>\n    <bogus a=\"1\"><b/></bogus>\n    <csv xpath=\"/a/@b\" delim=\";\" op=\"add\">x;y</csv>\n    <conditional>\n        <if cond=\"mod_loaded('A') and not mod_loaded('B')\">\n            <append xpath=\"/a\">\n                <b c=\"&amp;\"/>\n            </append>\n        </if>\n    </conditional>\n</config>";
+        let xml = ModletXML::new("Config/items.xml").read(source).unwrap();
+        let json = serde_json::to_string(&xml).unwrap();
+
+        assert_eq!(xml, serde_json::from_str(&json).unwrap());
+    }
 }
@@ -0,0 +1,279 @@
+use super::{command::Command, extension, CsvInstruction, InstructionSet, ModletXML};
+use crate::error::ModletError;
+use quick_xml::{
+    events::{BytesEnd, BytesStart, BytesText, Event},
+    Reader,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, fs};
+
+/// A structured patch file, such as `Config/items.yaml`:
+///
+/// ```yaml
+/// patches:
+///   - op: set
+///     xpath: /items/item[@name='gunPistol']/property[@name='Stacknumber']/@value
+///     value: 5
+///   - op: append
+///     xpath: /items
+///     children:
+///       - tag: item
+///         attributes: { name: myNewGun }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatchFile {
+    /// The root element of the equivalent XML file
+    #[serde(default = "default_root")]
+    root: String,
+    #[serde(default)]
+    patches: Vec<Patch>,
+}
+
+fn default_root() -> String {
+    String::from("config")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Patch {
+    /// The command, as it would be named in XML (e.g. `set`, `setattribute`, `insertAfter`)
+    op: String,
+    xpath: String,
+    /// The attribute to set (`setattribute` only)
+    name: Option<String>,
+    /// The text of a `set`, `setattribute` or `csv` command
+    value: Option<Scalar>,
+    /// The csv operation (`add` or `remove`)
+    csv: Option<String>,
+    delim: Option<char>,
+    /// The elements added by `append`, `insertAfter` and `insertBefore`
+    children: Option<Children>,
+}
+
+/// A value which may be written as a string, number or boolean
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scalar::Bool(value) => write!(f, "{value}"),
+            Scalar::Integer(value) => write!(f, "{value}"),
+            Scalar::Float(value) => write!(f, "{value}"),
+            Scalar::String(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// Child elements, either as raw XML or as structured elements
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Children {
+    Elements(Vec<Element>),
+    Xml(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Element {
+    tag: String,
+    #[serde(default)]
+    attributes: BTreeMap<String, Scalar>,
+    #[serde(default)]
+    children: Vec<Element>,
+    text: Option<Scalar>,
+}
+
+impl Element {
+    fn events(&self, events: &mut Vec<Event<'static>>) {
+        let mut start = BytesStart::new(self.tag.clone());
+        for (key, value) in &self.attributes {
+            start.push_attribute((key.as_str(), value.to_string().as_str()));
+        }
+
+        if self.children.is_empty() && self.text.is_none() {
+            events.push(Event::Empty(start));
+            return;
+        }
+
+        events.push(Event::Start(start));
+        if let Some(text) = &self.text {
+            events.push(Event::Text(BytesText::new(&text.to_string()).into_owned()));
+        }
+        for child in &self.children {
+            child.events(events);
+        }
+        events.push(Event::End(BytesEnd::new(self.tag.clone())));
+    }
+}
+
+impl ModletXML {
+    /// Compiles a structured patch file (YAML or TOML) into the commands its XML equivalent would contain
+    ///
+    /// # Errors
+    ///
+    /// * If the file does not exist, or cannot be read
+    /// * If the file is not valid YAML/TOML, or a patch is missing a required field
+    pub fn compile(mut self) -> Result<Self, ModletError> {
+        if !self.path.exists() {
+            return Err(ModletError::NotFound(self.path));
+        }
+
+        let source = fs::read_to_string(&self.path)?;
+        let invalid = |message: String| ModletError::InvalidPatchFile {
+            path: self.path.clone(),
+            message,
+        };
+        let patch_file: PatchFile = match extension(&self.path).as_str() {
+            "toml" => toml::from_str(&source).map_err(|err| invalid(err.to_string()))?,
+            _ => serde_yaml::from_str(&source).map_err(|err| invalid(err.to_string()))?,
+        };
+
+        self.commands = vec![Command::StartTag(Some(patch_file.root.clone()))];
+        for (index, patch) in patch_file.patches.iter().enumerate() {
+            let command =
+                compile_patch(patch).map_err(|message| invalid(format!("patch #{}: {message}", index + 1)))?;
+            self.commands.push(command);
+        }
+        self.root = Some(BytesStart::new(patch_file.root));
+
+        Ok(self)
+    }
+}
+
+fn compile_patch(patch: &Patch) -> Result<Command, String> {
+    let command = Command::parse(&patch.op);
+    let mut instruction = InstructionSet {
+        xpath: patch.xpath.as_bytes().to_vec(),
+        ..InstructionSet::new()
+    };
+
+    match &command {
+        Command::Append(_) | Command::InsertAfter(_) | Command::InsertBefore(_) => {
+            instruction.values = match &patch.children {
+                Some(Children::Elements(elements)) => {
+                    let mut events = Vec::new();
+                    elements.iter().for_each(|element| element.events(&mut events));
+                    events
+                }
+                Some(Children::Xml(xml)) => parse_xml(xml)?,
+                None => return Err(format!("`{}` requires `children`", patch.op)),
+            };
+        }
+        Command::Csv(_) => {
+            let delim = patch.delim.unwrap_or(',');
+            instruction.csv_op = match patch.csv.as_deref() {
+                Some("add") => Some(CsvInstruction::Add(delim)),
+                Some("remove") => Some(CsvInstruction::Remove(delim)),
+                Some(op) => return Err(format!("unknown csv operation `{op}` (expected `add` or `remove`)")),
+                None => return Err(String::from("`csv` requires `csv: add` or `csv: remove`")),
+            };
+            instruction.values = text(patch)?;
+        }
+        Command::Remove(_) | Command::RemoveAttribute(_) => (),
+        Command::Set(_) => instruction.values = text(patch)?,
+        Command::SetAttribute(_) => {
+            let name = patch.name.as_ref().ok_or("`setattribute` requires `name`")?;
+            instruction.attribute = Some(name.as_bytes().to_vec());
+            instruction.values = text(patch)?;
+        }
+        _ => return Err(format!("unknown op `{}`", patch.op)),
+    }
+
+    Ok(command.set(instruction))
+}
+
+fn text(patch: &Patch) -> Result<Vec<Event<'static>>, String> {
+    let value = patch
+        .value
+        .as_ref()
+        .ok_or_else(|| format!("`{}` requires `value`", patch.op))?
+        .to_string();
+
+    Ok(vec![Event::Text(BytesText::new(&value).into_owned())])
+}
+
+fn parse_xml(xml: &str) -> Result<Vec<Event<'static>>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut events = Vec::new();
+
+    reader.trim_text(true);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(event) => events.push(event.into_owned()),
+            Err(err) => return Err(format!("invalid `children` XML: {err}")),
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(commands: &[Command]) -> String {
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        commands.iter().for_each(|command| command.write(&mut writer).unwrap());
+
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[rstest]
+    #[case::set("{ op: set, xpath: /a/@b, value: 5 }", "<set xpath=\"/a/@b\">5</set>")]
+    #[case::set_attribute(
+        "{ op: setattribute, xpath: /a, name: b, value: 'x & y' }",
+        "<setattribute xpath=\"/a\" name=\"b\">x &amp; y</setattribute>"
+    )]
+    #[case::csv(
+        "{ op: csv, xpath: /a/@b, csv: add, value: 'x,y' }",
+        "<csv xpath=\"/a/@b\" delim=\",\" op=\"add\">x,y</csv>"
+    )]
+    #[case::remove("{ op: remove, xpath: \"/a[@name='b']\" }", "<remove xpath=\"/a[@name='b']\"/>")]
+    #[case::append_elements(
+        "{ op: append, xpath: /a, children: [{ tag: b, attributes: { name: c, value: 1 }, children: [{ tag: d }] }] }",
+        "<append xpath=\"/a\"><b name=\"c\" value=\"1\"><d/></b></append>"
+    )]
+    #[case::insert_after_xml(
+        "{ op: insertAfter, xpath: /a, children: '<b/><c>1</c>' }",
+        "<insertAfter xpath=\"/a\"><b/><c>1</c></insertAfter>"
+    )]
+    fn test_compile_patch(#[case] patch: &str, #[case] expected: &str) {
+        let patch: Patch = serde_yaml::from_str(patch).unwrap();
+
+        assert_eq!(expected, write(&[compile_patch(&patch).unwrap()]));
+    }
+
+    #[rstest]
+    #[case::unknown_op("{ op: frobnicate, xpath: /a }", "unknown op `frobnicate`")]
+    #[case::missing_value("{ op: set, xpath: /a }", "`set` requires `value`")]
+    #[case::missing_name("{ op: setattribute, xpath: /a, value: 1 }", "`setattribute` requires `name`")]
+    fn test_compile_patch_error(#[case] patch: &str, #[case] expected: &str) {
+        let patch: Patch = serde_yaml::from_str(patch).unwrap();
+
+        assert_eq!(expected, compile_patch(&patch).unwrap_err());
+    }
+
+    #[test]
+    fn test_toml() {
+        let patch_file: PatchFile = toml::from_str(
+            "[[patches]]\nop = \"set\"\nxpath = \"/items/item[@name='gunPistol']/@value\"\nvalue = 1.5\n",
+        )
+        .unwrap();
+        let command = compile_patch(&patch_file.patches[0]).unwrap();
+
+        assert_eq!("config", patch_file.root);
+        assert_eq!(
+            "<set xpath=\"/items/item[@name='gunPistol']/@value\">1.5</set>",
+            write(&[command])
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Serde helpers for the raw XML types held by commands.
+//! Byte strings are serialized as (lossy) UTF-8 strings, and XML events as the markup they represent,
+//! so the output is readable by scripts which know nothing about quick-xml.
+
+/// `Vec<u8>` as a string
+pub mod bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Ok(String::deserialize(deserializer)?.into_bytes())
+    }
+}
+
+/// `Option<Vec<u8>>` as an optional string
+pub mod option_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&String::from_utf8_lossy(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(String::into_bytes))
+    }
+}
+
+/// `Vec<Event>` as the XML fragment it represents
+pub mod events {
+    use quick_xml::{events::Event, Reader, Writer};
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(events: &[Event<'static>], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut writer = Writer::new(Vec::new());
+        for event in events {
+            writer.write_event(event).map_err(ser::Error::custom)?;
+        }
+
+        serializer.serialize_str(&String::from_utf8_lossy(&writer.into_inner()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Event<'static>>, D::Error> {
+        let xml = String::deserialize(deserializer)?;
+        let mut reader = Reader::from_str(&xml);
+        let mut events = Vec::new();
+
+        reader.trim_text(true);
+        loop {
+            match reader.read_event().map_err(de::Error::custom)? {
+                Event::Eof => break,
+                event => events.push(event.into_owned()),
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// `Option<BytesStart>` as the tag's content (e.g. `config version="2"`)
+pub mod option_start {
+    use quick_xml::events::BytesStart;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(start: &Option<BytesStart<'static>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match start {
+            Some(start) => serializer.serialize_some(&String::from_utf8_lossy(start)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BytesStart<'static>>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(|content| {
+            let name_len = content.find(char::is_whitespace).unwrap_or(content.len());
+            BytesStart::from_content(content, name_len)
+        }))
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    fmt::{Display, Formatter},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// The location of a parsed command within its source file
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// The 1-based column at which the command starts
+    pub column: usize,
+    /// The 1-based line on which the command starts
+    pub line: usize,
+    pub path: Arc<Path>,
+    /// The byte range of the command, from its opening `<` to the end of its closing tag
+    pub range: Range<usize>,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.path.display(), self.line, self.column)
+    }
+}
+
+/// A span resolved back to its source text
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snippet {
+    pub column: usize,
+    pub line: usize,
+    pub path: PathBuf,
+    /// The complete source lines covered by the span
+    pub text: String,
+}
+
+impl Display for Snippet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}:{}:{}", self.path.display(), self.line, self.column)?;
+
+        let width = (self.line + self.text.lines().count()).to_string().len();
+        for (number, line) in (self.line..).zip(self.text.lines()) {
+            writeln!(f, "{number:>width$} | {line}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+/// This module computes the commands which turn one document into another (the inverse of the `Simulator`).
+/// Elements are matched by tag and `name` attribute where that is unique among their siblings, and by position
+/// otherwise; reordering matched elements is not detected.
+use super::document::{Document, NodeId};
+use crate::{
+    error::ModletError,
+    modlet::{Command, InstructionSet},
+};
+use quick_xml::Writer;
+
+/// Returns the commands which, applied in order to `vanilla`, produce `edited`
+///
+/// # Errors
+///
+/// * If either document has no root element, or their root elements differ
+pub fn diff(vanilla: &Document, edited: &Document) -> Result<Vec<Command>, ModletError> {
+    let (Some(v), Some(e)) = (vanilla.root_element(), edited.root_element()) else {
+        return Err(ModletError::MissingRootElement);
+    };
+    let (v_name, e_name) = (vanilla.name(v).unwrap_or_default(), edited.name(e).unwrap_or_default());
+    if v_name != e_name {
+        return Err(ModletError::RootElementMismatch {
+            vanilla: v_name.to_string(),
+            edited: e_name.to_string(),
+        });
+    }
+
+    let mut differ = Differ {
+        commands: Vec::new(),
+        edited,
+        vanilla,
+    };
+    differ.element(v, e, &format!("/{v_name}"))?;
+
+    Ok(differ.commands)
+}
+
+/// How an element is identified among its siblings
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Key<'a> {
+    /// By the `name` attribute, which is unique among siblings with the same tag
+    Named(&'a str, &'a str),
+    /// By (1-based) position among siblings with the same tag
+    Indexed(&'a str, usize),
+}
+
+/// An element child of the node being diffed, as it stands after the commands emitted so far
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Sibling {
+    Edited(NodeId),
+    Vanilla(NodeId),
+}
+
+struct Differ<'a> {
+    commands: Vec<Command>,
+    edited: &'a Document,
+    vanilla: &'a Document,
+}
+
+impl<'a> Differ<'a> {
+    fn element(&mut self, v: NodeId, e: NodeId, xpath: &str) -> Result<(), ModletError> {
+        self.attributes(v, e, xpath)?;
+
+        let v_children = element_children(self.vanilla, v);
+        let e_children = element_children(self.edited, e);
+        if v_children.is_empty() && e_children.is_empty() {
+            let text = self.edited.text(e);
+            if self.vanilla.text(v) != text {
+                self.commands
+                    .push(Command::Set(InstructionSet::builder(xpath).text(text).build()?));
+            }
+            return Ok(());
+        }
+
+        let v_keys = keys(self.vanilla, &v_children);
+        let e_keys = keys(self.edited, &e_children);
+        // The vanilla element each edited element corresponds to, if any
+        let matches: Vec<Option<NodeId>> = e_keys
+            .iter()
+            .map(|key| v_keys.iter().position(|v_key| v_key == key).map(|i| v_children[i]))
+            .collect();
+
+        // Changes within matched elements come first, while every sibling is still where it is in vanilla
+        let mut siblings: Vec<Sibling> = v_children.iter().copied().map(Sibling::Vanilla).collect();
+        for (&e_child, v_child) in e_children.iter().zip(&matches) {
+            if let Some(v_child) = *v_child {
+                let child_xpath = format!("{xpath}/{}", self.step(&siblings, Sibling::Vanilla(v_child)));
+                self.element(v_child, e_child, &child_xpath)?;
+            }
+        }
+
+        for &v_child in &v_children {
+            if !matches.contains(&Some(v_child)) {
+                let child_xpath = format!("{xpath}/{}", self.step(&siblings, Sibling::Vanilla(v_child)));
+                self.commands
+                    .push(Command::Remove(InstructionSet::builder(child_xpath).build()?));
+                siblings.retain(|&sibling| sibling != Sibling::Vanilla(v_child));
+            }
+        }
+
+        // Runs of new elements are inserted after the preceding matched element, appended if nothing follows them,
+        // or inserted before the following one if they come first
+        let mut start = 0;
+        while start < e_children.len() {
+            if matches[start].is_some() {
+                start += 1;
+                continue;
+            }
+
+            let end = (start..e_children.len())
+                .find(|&i| matches[i].is_some())
+                .unwrap_or(e_children.len());
+            let new = &e_children[start..end];
+            let xml = self.xml(new)?;
+            let added = new.iter().copied().map(Sibling::Edited);
+
+            let command = match (
+                start.checked_sub(1).and_then(|i| matches[i]),
+                matches.get(end).copied().flatten(),
+            ) {
+                (_, None) => {
+                    siblings.extend(added);
+                    Command::Append(InstructionSet::builder(xpath).xml(xml).build()?)
+                }
+                (Some(previous), Some(_)) => {
+                    let anchor = format!("{xpath}/{}", self.step(&siblings, Sibling::Vanilla(previous)));
+                    let index = siblings.iter().position(|&s| s == Sibling::Vanilla(previous)).unwrap() + 1;
+                    siblings.splice(index..index, added);
+                    Command::InsertAfter(InstructionSet::builder(anchor).xml(xml).build()?)
+                }
+                (None, Some(next)) => {
+                    let anchor = format!("{xpath}/{}", self.step(&siblings, Sibling::Vanilla(next)));
+                    let index = siblings.iter().position(|&s| s == Sibling::Vanilla(next)).unwrap();
+                    siblings.splice(index..index, added);
+                    Command::InsertBefore(InstructionSet::builder(anchor).xml(xml).build()?)
+                }
+            };
+            self.commands.push(command);
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    fn attributes(&mut self, v: NodeId, e: NodeId, xpath: &str) -> Result<(), ModletError> {
+        for (name, value) in self.edited.attributes(e) {
+            let command = match self.vanilla.attribute(v, name) {
+                Some(old) if old == value => continue,
+                Some(_) => Command::Set(
+                    InstructionSet::builder(format!("{xpath}/@{name}"))
+                        .text(value)
+                        .build()?,
+                ),
+                None => Command::SetAttribute(InstructionSet::builder(xpath).attribute(name).text(value).build()?),
+            };
+            self.commands.push(command);
+        }
+
+        for (name, _) in self.vanilla.attributes(v) {
+            if self.edited.attribute(e, name).is_none() {
+                let is = InstructionSet::builder(format!("{xpath}/@{name}")).build()?;
+                self.commands.push(Command::RemoveAttribute(is));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn document(&self, sibling: Sibling) -> (&'a Document, NodeId) {
+        match sibling {
+            Sibling::Edited(id) => (self.edited, id),
+            Sibling::Vanilla(id) => (self.vanilla, id),
+        }
+    }
+
+    /// The xpath step selecting `target` among `siblings`, preferring its name over its position
+    fn step(&self, siblings: &[Sibling], target: Sibling) -> String {
+        let (document, id) = self.document(target);
+        let tag = document.name(id).unwrap_or_default();
+        let name = document.attribute(id, "name");
+        let same_tag: Vec<Sibling> = siblings
+            .iter()
+            .copied()
+            .filter(|&sibling| {
+                let (document, id) = self.document(sibling);
+                document.name(id) == Some(tag)
+            })
+            .collect();
+
+        if same_tag.len() == 1 {
+            return tag.to_string();
+        }
+        if let Some(name) = name {
+            let same_name = same_tag.iter().filter(|&&sibling| {
+                let (document, id) = self.document(sibling);
+                document.attribute(id, "name") == Some(name)
+            });
+            if same_name.count() == 1 {
+                return format!("{tag}[@name={}]", quote(name));
+            }
+        }
+
+        let position = same_tag
+            .iter()
+            .position(|&sibling| sibling == target)
+            .unwrap_or_default()
+            + 1;
+        format!("{tag}[{position}]")
+    }
+
+    /// Writes edited elements as XML
+    fn xml(&self, ids: &[NodeId]) -> Result<String, ModletError> {
+        let mut writer = Writer::new(Vec::new());
+        for &id in ids {
+            self.edited.write_node(&mut writer, id)?;
+        }
+
+        Ok(String::from_utf8(writer.into_inner()).map_err(|err| err.utf8_error())?)
+    }
+}
+
+fn element_children(document: &Document, id: NodeId) -> Vec<NodeId> {
+    document
+        .children(id)
+        .iter()
+        .copied()
+        .filter(|&child| document.name(child).is_some())
+        .collect()
+}
+
+fn keys<'a>(document: &'a Document, ids: &[NodeId]) -> Vec<Key<'a>> {
+    let tags: Vec<&str> = ids.iter().map(|&id| document.name(id).unwrap_or_default()).collect();
+    let names: Vec<Option<&str>> = ids.iter().map(|&id| document.attribute(id, "name")).collect();
+
+    (0..ids.len())
+        .map(|i| {
+            let unique_name = names[i].filter(|&name| {
+                (0..ids.len())
+                    .filter(|&j| tags[j] == tags[i] && names[j] == Some(name))
+                    .count()
+                    == 1
+            });
+            match unique_name {
+                Some(name) => Key::Named(tags[i], name),
+                None => Key::Indexed(tags[i], tags[..=i].iter().filter(|&&tag| tag == tags[i]).count()),
+            }
+        })
+        .collect()
+}
+
+/// Quotes an xpath string literal
+fn quote(value: &str) -> String {
+    if value.contains('\'') {
+        format!("\"{value}\"")
+    } else {
+        format!("'{value}'")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::Simulator;
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    const VANILLA: &str = r#"<items>
+            <item name="gunPistol">
+                <property name="Tags" value="weapon,ranged"/>
+                <property name="Stacknumber" value="1"/>
+            </item>
+            <item name="ammo9mmBullet">
+                <property name="Stacknumber" value="300"/>
+            </item>
+            <item name="resourceWood"><description>Wood</description></item>
+        </items>"#;
+
+    #[rstest]
+    #[case::unchanged(VANILLA, VANILLA, &[])]
+    #[case::set(
+        VANILLA,
+        r#"<items>
+            <item name="gunPistol">
+                <property name="Tags" value="weapon,ranged"/>
+                <property name="Stacknumber" value="5"/>
+            </item>
+            <item name="ammo9mmBullet">
+                <property name="Stacknumber" value="300"/>
+            </item>
+            <item name="resourceWood"><description>Logs</description></item>
+        </items>"#,
+        &[
+            "<set xpath=\"/items/item[@name='gunPistol']/property[@name='Stacknumber']/@value\">5</set>",
+            "<set xpath=\"/items/item[@name='resourceWood']/description\">Logs</set>",
+        ]
+    )]
+    #[case::attributes(
+        VANILLA,
+        r#"<items>
+            <item name="gunPistol" tier="2">
+                <property name="Tags"/>
+                <property name="Stacknumber" value="1"/>
+            </item>
+            <item name="ammo9mmBullet">
+                <property name="Stacknumber" value="300"/>
+            </item>
+            <item name="resourceWood"><description>Wood</description></item>
+        </items>"#,
+        &[
+            "<setattribute xpath=\"/items/item[@name='gunPistol']\" name=\"tier\">2</setattribute>",
+            "<removeattribute xpath=\"/items/item[@name='gunPistol']/property[@name='Tags']/@value\"/>",
+        ]
+    )]
+    #[case::structure(
+        VANILLA,
+        r#"<items>
+            <item name="gunNew"/>
+            <item name="gunPistol">
+                <property name="Tags" value="weapon,ranged"/>
+                <property name="Stacknumber" value="1"/>
+                <property name="Tier" value="1"/>
+            </item>
+            <item name="resourceWood"><description>Wood</description></item>
+            <item name="resourceStone"/>
+        </items>"#,
+        &[
+            "<append xpath=\"/items/item[@name='gunPistol']\"><property name=\"Tier\" value=\"1\"/></append>",
+            "<remove xpath=\"/items/item[@name='ammo9mmBullet']\"/>",
+            "<insertBefore xpath=\"/items/item[@name='gunPistol']\"><item name=\"gunNew\"/></insertBefore>",
+            "<append xpath=\"/items\"><item name=\"resourceStone\"/></append>",
+        ]
+    )]
+    #[case::positional(
+        "<a><b/><b/><c/></a>",
+        "<a><b/><d/><c/></a>",
+        &["<remove xpath=\"/a/b[2]\"/>", "<insertAfter xpath=\"/a/b\"><d/></insertAfter>"]
+    )]
+    fn test_diff(#[case] vanilla: &str, #[case] edited: &str, #[case] expected: &[&str]) {
+        let vanilla = Document::parse(vanilla).unwrap();
+        let edited = Document::parse(edited).unwrap();
+        let commands = diff(&vanilla, &edited).unwrap();
+
+        let written: Vec<String> = commands
+            .iter()
+            .map(|command| {
+                let mut writer = Writer::new(Vec::new());
+                command.write(&mut writer).unwrap();
+                String::from_utf8(writer.into_inner()).unwrap()
+            })
+            .collect();
+        assert_eq!(expected, written);
+
+        // Applying the commands to vanilla must produce the edited document
+        let mut simulator = Simulator {
+            annotate: false,
+            config_dir: PathBuf::new(),
+            documents: BTreeMap::from([(PathBuf::from("items.xml"), vanilla)]),
+            loaded_mods: Vec::new(),
+        };
+        for command in &commands {
+            simulator.apply_command("items.xml", command).unwrap();
+        }
+        assert_eq!(
+            edited.to_xml().unwrap(),
+            simulator.document("items.xml").unwrap().to_xml().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let items = Document::parse("<items/>").unwrap();
+        let blocks = Document::parse("<blocks/>").unwrap();
+
+        assert!(matches!(
+            diff(&items, &Document::default()),
+            Err(ModletError::MissingRootElement)
+        ));
+        assert!(matches!(
+            diff(&items, &blocks),
+            Err(ModletError::RootElementMismatch { vanilla, edited }) if vanilla == "items" && edited == "blocks"
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+/// This module contains a minimal, mutable XML document tree.
+/// Nodes live in an arena and are addressed by `NodeId`; removed nodes are simply detached from their parent.
+use crate::{
+    error::{ModletError, ParseErrorKind},
+    modlet::line_column,
+};
+use quick_xml::{
+    events::{BytesDecl, BytesStart, BytesText, Event},
+    reader::Reader,
+    Writer,
+};
+use std::{collections::BTreeMap, fs, io::Write, path::Path, str};
+
+#[derive(Debug, Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NodeKind {
+    Comment(String),
+    Document,
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    Text(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+/// Represents an XML document (such as a vanilla `items.xml`)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Document {
+    /// Notes written as comments before the nodes they're attached to (e.g. which modlet changed the node)
+    annotations: BTreeMap<NodeId, Vec<String>>,
+    nodes: Vec<Node>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            annotations: BTreeMap::new(),
+            nodes: vec![Node::new(NodeKind::Document)],
+        }
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// # Errors
+    ///
+    /// * If the file cannot be read, or is not valid XML (reporting the line and column of the problem)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ModletError> {
+        let xml = fs::read_to_string(path.as_ref())?;
+
+        Self::parse(&xml).map_err(|err| match err {
+            ModletError::InvalidDocument { position, source } => {
+                let (line, column) = line_column(&xml, position);
+                ModletError::Parse {
+                    path: path.as_ref().to_path_buf(),
+                    line,
+                    column,
+                    kind: ParseErrorKind::Reader(source),
+                }
+            }
+            err => err,
+        })
+    }
+
+    /// # Errors
+    ///
+    /// * If the XML is not valid, reporting the (byte) position of the problem
+    pub fn parse(xml: &str) -> Result<Self, ModletError> {
+        let mut document = Self::new();
+        let mut reader = Reader::from_str(xml);
+        let mut events = Vec::new();
+
+        reader.trim_text(true);
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Eof) => break,
+                Ok(event) => events.push(event),
+                Err(source) => {
+                    return Err(ModletError::InvalidDocument {
+                        position: reader.buffer_position(),
+                        source,
+                    })
+                }
+            }
+        }
+
+        let root = document.root();
+        for node in document.import(&events)? {
+            document.append_child(root, node);
+        }
+
+        Ok(document)
+    }
+
+    /// The document node, parent of the root element
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The first element below the document node (e.g. `<items>`)
+    pub fn root_element(&self) -> Option<NodeId> {
+        self.children(self.root())
+            .iter()
+            .copied()
+            .find(|&id| self.name(id).is_some())
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn kind(&self, id: NodeId) -> &NodeKind {
+        &self.nodes[id.0].kind
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+
+    /// Returns all descendants of a node, in document order
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut descendants = Vec::new();
+        let mut stack: Vec<NodeId> = self.children(id).iter().rev().copied().collect();
+
+        while let Some(id) = stack.pop() {
+            descendants.push(id);
+            stack.extend(self.children(id).iter().rev());
+        }
+
+        descendants
+    }
+
+    /// Returns the tag name of an element, or `None` for other nodes
+    pub fn name(&self, id: NodeId) -> Option<&str> {
+        match self.kind(id) {
+            NodeKind::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn attributes(&self, id: NodeId) -> &[(String, String)] {
+        match self.kind(id) {
+            NodeKind::Element { attributes, .. } => attributes,
+            _ => &[],
+        }
+    }
+
+    pub fn attribute(&self, id: NodeId, name: &str) -> Option<&str> {
+        self.attributes(id)
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets (or adds) an attribute on an element
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) {
+        if let NodeKind::Element { attributes, .. } = &mut self.nodes[id.0].kind {
+            match attributes.iter_mut().find(|(key, _)| key == name) {
+                Some((_, old_value)) => *old_value = value.to_owned(),
+                None => attributes.push((name.to_owned(), value.to_owned())),
+            }
+        }
+    }
+
+    /// Removes an attribute from an element, returning whether it existed
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) -> bool {
+        if let NodeKind::Element { attributes, .. } = &mut self.nodes[id.0].kind {
+            let count = attributes.len();
+            attributes.retain(|(key, _)| key != name);
+            return attributes.len() != count;
+        }
+
+        false
+    }
+
+    /// Attaches a note to a node, which is written as a comment immediately before it
+    pub fn annotate(&mut self, id: NodeId, note: impl Into<String>) {
+        self.annotations.entry(id).or_default().push(note.into());
+    }
+
+    pub fn annotations(&self, id: NodeId) -> &[String] {
+        self.annotations.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the concatenated text of a node and all of its descendants
+    pub fn text(&self, id: NodeId) -> String {
+        match self.kind(id) {
+            NodeKind::Text(text) => text.clone(),
+            NodeKind::Comment(_) => String::new(),
+            _ => self
+                .descendants(id)
+                .into_iter()
+                .filter_map(|id| match self.kind(id) {
+                    NodeKind::Text(text) => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Replaces the content of a node with the given text
+    pub fn set_text(&mut self, id: NodeId, text: &str) {
+        if let NodeKind::Text(old_text) = &mut self.nodes[id.0].kind {
+            *old_text = text.to_owned();
+            return;
+        }
+
+        for child in self.children(id).to_vec() {
+            self.detach(child);
+        }
+        let text = self.create(NodeKind::Text(text.to_owned()));
+        self.append_child(id, text);
+    }
+
+    /// Creates a new, detached node
+    pub fn create(&mut self, kind: NodeKind) -> NodeId {
+        self.nodes.push(Node::new(kind));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.detach(child);
+        self.nodes[child.0].parent = Some(parent);
+        self.nodes[parent.0].children.push(child);
+    }
+
+    pub fn insert_before(&mut self, sibling: NodeId, node: NodeId) {
+        self.insert_at(sibling, node, 0);
+    }
+
+    pub fn insert_after(&mut self, sibling: NodeId, node: NodeId) {
+        self.insert_at(sibling, node, 1);
+    }
+
+    fn insert_at(&mut self, sibling: NodeId, node: NodeId, offset: usize) {
+        let Some(parent) = self.parent(sibling) else {
+            return;
+        };
+
+        self.detach(node);
+        let index = self.nodes[parent.0]
+            .children
+            .iter()
+            .position(|&id| id == sibling)
+            .unwrap_or_default();
+        self.nodes[node.0].parent = Some(parent);
+        self.nodes[parent.0].children.insert(index + offset, node);
+    }
+
+    /// Removes a node (and its descendants) from the tree
+    pub fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.nodes[id.0].parent.take() {
+            self.nodes[parent.0].children.retain(|&child| child != id);
+        }
+    }
+
+    /// Creates detached nodes from a list of XML events, returning the top-level nodes
+    pub fn import(&mut self, events: &[Event]) -> Result<Vec<NodeId>, ModletError> {
+        let mut nodes = Vec::new();
+        let mut stack: Vec<NodeId> = Vec::new();
+
+        for event in events {
+            let node = match event {
+                Event::Start(start) | Event::Empty(start) => {
+                    self.create(element_kind(start).map_err(ModletError::InvalidXml)?)
+                }
+                Event::End(end) => {
+                    stack.pop().ok_or_else(|| {
+                        let tag = String::from_utf8_lossy(end.name().as_ref()).into_owned();
+                        ModletError::InvalidXml(quick_xml::Error::UnexpectedToken(format!("</{tag}>")))
+                    })?;
+                    continue;
+                }
+                Event::Text(text) => self.create(NodeKind::Text(
+                    text.unescape().map_err(ModletError::InvalidXml)?.into_owned(),
+                )),
+                Event::CData(cdata) => self.create(NodeKind::Text(str::from_utf8(cdata)?.to_owned())),
+                Event::Comment(comment) => self.create(NodeKind::Comment(str::from_utf8(comment)?.to_owned())),
+                _ => continue,
+            };
+
+            match stack.last() {
+                Some(&parent) => self.append_child(parent, node),
+                None => nodes.push(node),
+            }
+
+            if let Event::Start(_) = event {
+                stack.push(node);
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    /// Writes the document as XML
+    pub fn write(&self, writer: &mut Writer<impl Write>) -> Result<(), ModletError> {
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+        self.write_annotations(writer, self.root())?;
+        self.children(self.root())
+            .iter()
+            .try_for_each(|&child| self.write_node(writer, child))
+    }
+
+    pub fn write_node(&self, writer: &mut Writer<impl Write>, id: NodeId) -> Result<(), ModletError> {
+        self.write_annotations(writer, id)?;
+
+        match self.kind(id) {
+            NodeKind::Element { name, attributes } => {
+                let mut start = BytesStart::new(name.as_str());
+                for (key, value) in attributes {
+                    start.push_attribute((key.as_str(), value.as_str()));
+                }
+
+                if self.children(id).is_empty() {
+                    writer.write_event(Event::Empty(start))?;
+                } else {
+                    let end = start.to_end().into_owned();
+                    writer.write_event(Event::Start(start))?;
+                    for &child in self.children(id) {
+                        self.write_node(writer, child)?;
+                    }
+                    writer.write_event(Event::End(end))?;
+                }
+            }
+            NodeKind::Text(text) => writer.write_event(Event::Text(BytesText::new(text)))?,
+            NodeKind::Comment(comment) => writer.write_event(Event::Comment(BytesText::from_escaped(comment)))?,
+            NodeKind::Document => (),
+        }
+
+        Ok(())
+    }
+
+    fn write_annotations(&self, writer: &mut Writer<impl Write>, id: NodeId) -> Result<(), ModletError> {
+        for note in self.annotations(id) {
+            // `--` may not appear within a comment
+            let comment = format!(" {} ", note.replace("--", "- -"));
+            writer.write_event(Event::Comment(BytesText::from_escaped(comment)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the document as an indented XML string
+    pub fn to_xml(&self) -> Result<String, ModletError> {
+        let mut writer = Writer::new_with_indent(Vec::new(), b' ', 4);
+        self.write(&mut writer)?;
+
+        Ok(String::from_utf8(writer.into_inner()).map_err(|err| err.utf8_error())?)
+    }
+}
+
+impl Node {
+    fn new(kind: NodeKind) -> Self {
+        Self {
+            kind,
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+}
+
+fn element_kind(start: &BytesStart) -> Result<NodeKind, quick_xml::Error> {
+    let name = str::from_utf8(start.name().as_ref())?.to_owned();
+    let mut attributes = Vec::new();
+
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        attributes.push((
+            str::from_utf8(attribute.key.as_ref())?.to_owned(),
+            attribute.unescape_value()?.into_owned(),
+        ));
+    }
+
+    Ok(NodeKind::Element { name, attributes })
+}
@@ -0,0 +1,569 @@
+/// This module contains the implementation of the patch `Simulator`.
+/// The `Simulator` applies a modlet's commands to the vanilla game configs (found in the game's `Data/Config`
+/// directory), producing an in-memory copy of each document as the game would see it once the modlet is loaded.
+use crate::{
+    error::ModletError,
+    modlet::{Command, CsvInstruction, InstructionSet, Modlet},
+    xpath,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str,
+};
+
+mod diff;
+mod document;
+mod select;
+pub use diff::diff;
+pub use document::{Document, Node, NodeId, NodeKind};
+pub use select::{select, select_union, string_value, Selection};
+
+/// The effect of applying a single command
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Effect {
+    /// The number of selected nodes (or attributes) the command actually changed
+    pub changed: usize,
+    /// The number of nodes (or attributes) the command's xpath selected
+    pub matched: usize,
+}
+
+impl Effect {
+    /// Whether the command matched something, but changed none of it (e.g. a `set` to the current value)
+    pub fn is_noop(&self) -> bool {
+        self.matched > 0 && self.changed == 0
+    }
+}
+
+/// The result of applying a single command
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchOutcome {
+    pub command: Command,
+    pub effect: Effect,
+    pub file: PathBuf,
+}
+
+/// Applies modlet commands to the vanilla game configs
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    /// Whether changed nodes are annotated with the modlet and command which changed them
+    annotate: bool,
+    config_dir: PathBuf,
+    documents: BTreeMap<PathBuf, Document>,
+    /// Mod names considered loaded when evaluating `<conditional>` blocks
+    loaded_mods: Vec<String>,
+}
+
+impl Simulator {
+    /// # Errors
+    ///
+    /// * If the game directory has no `Data/Config` directory
+    pub fn new(game_directory: impl AsRef<Path>) -> Result<Self, ModletError> {
+        let config_dir = game_directory.as_ref().join("Data").join("Config");
+        if !config_dir.is_dir() {
+            return Err(ModletError::InvalidGameDirectory(game_directory.as_ref().to_path_buf()));
+        }
+
+        Ok(Self {
+            annotate: false,
+            config_dir,
+            documents: BTreeMap::new(),
+            loaded_mods: Vec::new(),
+        })
+    }
+
+    /// Sets the names of the mods `mod_loaded()` conditions should consider loaded
+    pub fn set_loaded_mods(&mut self, mods: impl IntoIterator<Item = impl ToString>) {
+        self.loaded_mods = mods.into_iter().map(|name| name.to_string()).collect();
+    }
+
+    /// Annotates each node changed by `apply()` with a comment naming the modlet and command which changed it
+    pub fn set_annotate(&mut self, annotate: bool) {
+        self.annotate = annotate;
+    }
+
+    /// Flattens `<conditional>` blocks, returning the commands the game would actually apply
+    pub fn active_commands<'a>(&self, commands: &'a [Command]) -> Vec<&'a Command> {
+        commands
+            .iter()
+            .flat_map(|command| match command {
+                Command::Conditional(branches) => branches
+                    .iter()
+                    .find(|branch| match &branch.condition {
+                        Some(condition) => condition.evaluate(&self.loaded_mods),
+                        None => true,
+                    })
+                    .map(|branch| self.active_commands(&branch.commands))
+                    .unwrap_or_default(),
+                command => vec![command],
+            })
+            .collect()
+    }
+
+    /// The vanilla `Data/Config` directory
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    /// Returns the (patched) document for a config file, loading it from the vanilla configs if necessary.
+    /// Files which don't exist in the vanilla configs are treated as empty documents.
+    pub fn document(&mut self, file: impl AsRef<Path>) -> Result<&mut Document, ModletError> {
+        let file = file.as_ref();
+        if !self.documents.contains_key(file) {
+            let vanilla = self.config_dir.join(file);
+            let document = if vanilla.is_file() {
+                Document::load(vanilla)?
+            } else {
+                Document::new()
+            };
+            self.documents.insert(file.to_owned(), document);
+        }
+
+        Ok(self.documents.get_mut(file).unwrap())
+    }
+
+    /// Loads every vanilla config file (`Data/Config/**/*.xml`), so that unpatched files are included in
+    /// `documents()`
+    pub fn load_all(&mut self) -> Result<(), ModletError> {
+        let pattern = self.config_dir.join("**").join("*.xml");
+        for path in glob::glob(&pattern.to_string_lossy())? {
+            let path = path?;
+            if let Ok(file) = path.strip_prefix(&self.config_dir) {
+                self.document(file)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// All documents loaded (and patched) so far, keyed by their path relative to `Data/Config`
+    pub fn documents(&self) -> &BTreeMap<PathBuf, Document> {
+        &self.documents
+    }
+
+    /// Applies every command of a modlet, in file order.
+    /// Commands in a packaged bundle are attributed to the modlet named by the preceding `Included from` comment.
+    pub fn apply(&mut self, modlet: &Modlet) -> Result<Vec<PatchOutcome>, ModletError> {
+        let mut outcomes = Vec::new();
+
+        for xml in &modlet.xmls {
+            let file = xml.filename();
+            let mut source = modlet.name().into_owned();
+            for command in self.active_commands(&xml.commands) {
+                if let Some(name) = command.included_from() {
+                    source = name.to_string();
+                }
+
+                let note = self.annotate.then(|| annotation(&source, &file, command));
+                if let Some(effect) = self.patch(&file, command, note.as_deref())? {
+                    outcomes.push(PatchOutcome {
+                        command: command.clone(),
+                        effect,
+                        file: file.to_path_buf(),
+                    });
+                }
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Applies a single command to a config file, returning how many nodes its xpath selected and changed.
+    /// Returns `None` for commands which don't patch anything (such as comments).
+    pub fn apply_command(&mut self, file: impl AsRef<Path>, command: &Command) -> Result<Option<Effect>, ModletError> {
+        self.patch(file.as_ref(), command, None)
+    }
+
+    /// Returns the number of nodes (or attributes) a command's xpath selects, without applying it.
+    /// Commands which don't patch anything (such as comments) select nothing.
+    pub fn matches(&mut self, file: impl AsRef<Path>, command: &Command) -> Result<usize, ModletError> {
+        let Some(is) = command.instructions() else {
+            return Ok(0);
+        };
+
+        let paths = location_paths(is)?;
+
+        Ok(select_union(self.document(file)?, &paths).len())
+    }
+
+    /// Applies a single command, attaching `note` (if any) to each node it changes
+    fn patch(&mut self, file: &Path, command: &Command, note: Option<&str>) -> Result<Option<Effect>, ModletError> {
+        if let Command::Conditional(_) = command {
+            for command in self.active_commands(std::slice::from_ref(command)) {
+                self.patch(file, command, note)?;
+            }
+            return Ok(None);
+        }
+
+        let Some(is) = command.instructions() else {
+            return Ok(None);
+        };
+
+        let paths = location_paths(is)?;
+        let document = self.document(file)?;
+        let selections = select_union(document, &paths);
+
+        let mut effect = Effect {
+            changed: 0,
+            matched: selections.len(),
+        };
+        // Removing an attribute the selected elements don't have is a no-op rather than a dead patch, so the
+        // elements count as matched
+        if selections.is_empty()
+            && matches!(command, Command::RemoveAttribute(_))
+            && paths.iter().all(|path| path.target_attribute().is_some())
+        {
+            let elements: Vec<_> = paths
+                .iter()
+                .map(|path| xpath::LocationPath {
+                    absolute: path.absolute,
+                    steps: path.steps[..path.steps.len() - 1].to_vec(),
+                })
+                .collect();
+            effect.matched = select_union(document, &elements).len();
+        }
+        for selection in &selections {
+            let id = annotation_target(document, command, selection);
+            if apply_selection(document, command, is, selection)? {
+                effect.changed += 1;
+                if let Some(note) = note {
+                    document.annotate(id, note);
+                }
+            }
+        }
+
+        Ok(Some(effect))
+    }
+}
+
+fn location_paths(is: &InstructionSet) -> Result<Vec<xpath::LocationPath>, ModletError> {
+    let xpath = str::from_utf8(&is.xpath)?;
+
+    xpath::parse_union(xpath).map_err(|source| ModletError::InvalidXPath {
+        xpath: xpath.to_string(),
+        source,
+    })
+}
+
+/// Describes a command for annotating the nodes it changes, e.g. `MyModlet: set /items/item/@value (items.xml:3)`
+fn annotation(source: &str, file: &Path, command: &Command) -> String {
+    let xpath = command
+        .instructions()
+        .map(|is| String::from_utf8_lossy(&is.xpath).into_owned())
+        .unwrap_or_default();
+    let mut note = format!("{source}: {command} {xpath}");
+    if let Some(span) = command.span() {
+        note.push_str(&format!(" ({}:{})", file.display(), span.line));
+    }
+
+    note
+}
+
+/// The element an annotation is attached to: the selected element itself, the owner of a selected attribute or
+/// text node, or the parent of a removed element
+fn annotation_target(document: &Document, command: &Command, selection: &Selection) -> NodeId {
+    match selection {
+        Selection::Attribute(id, _) => *id,
+        Selection::Node(id) => match (command, document.kind(*id)) {
+            (Command::Remove(_), _) | (_, NodeKind::Text(_) | NodeKind::Comment(_)) => {
+                document.parent(*id).unwrap_or(*id)
+            }
+            _ => *id,
+        },
+    }
+}
+
+/// Applies a command to a single selected node (or attribute), returning whether anything changed
+fn apply_selection(
+    document: &mut Document,
+    command: &Command,
+    is: &InstructionSet,
+    selection: &Selection,
+) -> Result<bool, ModletError> {
+    let changed = match (command, selection) {
+        (Command::Append(_), Selection::Node(id)) => {
+            let nodes = document.import(&is.values)?;
+            for &node in &nodes {
+                document.append_child(*id, node);
+            }
+            !nodes.is_empty()
+        }
+        (Command::Append(_), Selection::Attribute(id, name)) => {
+            let text = is.text();
+            let value = format!("{}{text}", document.attribute(*id, name).unwrap_or_default());
+            document.set_attribute(*id, name, &value);
+            !text.is_empty()
+        }
+        (Command::InsertAfter(_), Selection::Node(id)) => {
+            let nodes = document.import(&is.values)?;
+            let mut anchor = *id;
+            for &node in &nodes {
+                document.insert_after(anchor, node);
+                anchor = node;
+            }
+            !nodes.is_empty()
+        }
+        (Command::InsertBefore(_), Selection::Node(id)) => {
+            let nodes = document.import(&is.values)?;
+            for &node in &nodes {
+                document.insert_before(*id, node);
+            }
+            !nodes.is_empty()
+        }
+        (Command::Remove(_), Selection::Node(id)) => {
+            document.detach(*id);
+            true
+        }
+        (Command::Remove(_) | Command::RemoveAttribute(_), Selection::Attribute(id, name)) => {
+            document.remove_attribute(*id, name)
+        }
+        (Command::Set(_), Selection::Node(id)) => {
+            let text = is.text();
+            let unchanged = document
+                .children(*id)
+                .iter()
+                .all(|&child| matches!(document.kind(child), NodeKind::Text(_)))
+                && document.text(*id) == text;
+            document.set_text(*id, &text);
+            !unchanged
+        }
+        (Command::Set(_), Selection::Attribute(id, name)) => set_attribute(document, *id, name, &is.text()),
+        (Command::SetAttribute(_), Selection::Node(id)) => {
+            let name = is.attribute.as_ref().ok_or_else(|| ModletError::MissingAttribute {
+                command: command.to_string(),
+                attribute: "name",
+            })?;
+            set_attribute(document, *id, str::from_utf8(name)?, &is.text())
+        }
+        (Command::Csv(_), selection) => {
+            let csv_op = is.csv_op.as_ref().ok_or_else(|| ModletError::MissingAttribute {
+                command: command.to_string(),
+                attribute: "op",
+            })?;
+            let current = string_value(document, selection);
+            let value = apply_csv(&current, csv_op, &is.csv_values());
+            match selection {
+                Selection::Node(id) => document.set_text(*id, &value),
+                Selection::Attribute(id, name) => document.set_attribute(*id, name, &value),
+            }
+            // Compare entries rather than text, so that reformatting the list (e.g. dropping spaces) isn't a change
+            apply_csv(&current, csv_op, &[]) != value
+        }
+        // Anything else (e.g. inserting next to an attribute) is ignored by the game as well
+        _ => false,
+    };
+
+    Ok(changed)
+}
+
+/// Sets an attribute, returning whether its value changed
+fn set_attribute(document: &mut Document, id: NodeId, name: &str, value: &str) -> bool {
+    let changed = document.attribute(id, name) != Some(value);
+    document.set_attribute(id, name, value);
+
+    changed
+}
+
+/// Adds or removes entries from a delimited list, using the command's delimiter for both the current value and
+/// the entries
+fn apply_csv(current: &str, csv_op: &CsvInstruction, values: &[String]) -> String {
+    let delim = *csv_op.delim();
+    let mut entries: Vec<&str> = current
+        .split(delim)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect();
+
+    match csv_op {
+        CsvInstruction::Add(_) => {
+            for value in values {
+                if !entries.contains(&value.as_str()) {
+                    entries.push(value);
+                }
+            }
+        }
+        CsvInstruction::Remove(_) => entries.retain(|entry| !values.iter().any(|value| value == entry)),
+    }
+
+    entries.join(&delim.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::{BytesStart, BytesText, Event};
+
+    const ITEMS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <items>
+            <item name="gunPistol">
+                <property name="Tags" value="weapon,ranged"/>
+                <property name="Groups" value="weapon|ranged|melee"/>
+                <property name="Stacknumber" value="1"/>
+            </item>
+            <item name="ammo9mmBullet">
+                <property name="Stacknumber" value="300"/>
+            </item>
+        </items>"#;
+
+    fn simulator() -> Simulator {
+        let mut documents = BTreeMap::new();
+        documents.insert(PathBuf::from("items.xml"), Document::parse(ITEMS).unwrap());
+
+        Simulator {
+            annotate: false,
+            config_dir: PathBuf::new(),
+            documents,
+            loaded_mods: Vec::new(),
+        }
+    }
+
+    fn instruction_set(xpath: &str, text: &str) -> InstructionSet {
+        InstructionSet {
+            xpath: xpath.as_bytes().to_vec(),
+            values: vec![Event::Text(BytesText::new(text).into_owned())],
+            ..InstructionSet::new()
+        }
+    }
+
+    fn attribute(simulator: &mut Simulator, xpath: &str) -> Vec<String> {
+        let document = simulator.document("items.xml").unwrap();
+        select(document, &xpath::parse(xpath).unwrap())
+            .iter()
+            .map(|selection| string_value(document, selection))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::set(
+        Command::Set(instruction_set("/items/item[@name='gunPistol']/property[@name='Stacknumber']/@value", "5")),
+        "//item[@name='gunPistol']/property[@name='Stacknumber']/@value",
+        vec!["5"]
+    )]
+    #[case::csv_add(
+        Command::Csv(InstructionSet { csv_op: Some(CsvInstruction::Add(',')), ..instruction_set("//property[@name='Tags']/@value", "melee,weapon") }),
+        "//property[@name='Tags']/@value",
+        vec!["weapon,ranged,melee"]
+    )]
+    #[case::csv_add_pipe(
+        Command::Csv(InstructionSet { csv_op: Some(CsvInstruction::Add('|')), ..instruction_set("//property[@name='Tags']/@value", "melee|weapon,ranged") }),
+        "//property[@name='Tags']/@value",
+        vec!["weapon,ranged|melee"]
+    )]
+    #[case::csv_remove_pipe(
+        Command::Csv(InstructionSet { csv_op: Some(CsvInstruction::Remove('|')), ..instruction_set("//property[@name='Groups']/@value", " ranged |sniper") }),
+        "//property[@name='Groups']/@value",
+        vec!["weapon|melee"]
+    )]
+    #[case::remove(
+        Command::Remove(instruction_set("/items/item[starts-with(@name, 'ammo')]", "")),
+        "/items/item/@name",
+        vec!["gunPistol"]
+    )]
+    #[case::remove_attribute(
+        Command::RemoveAttribute(instruction_set("/items/item[2]/@name", "")),
+        "/items/item/@name",
+        vec!["gunPistol"]
+    )]
+    fn test_apply_command(#[case] command: Command, #[case] xpath: &str, #[case] expected: Vec<&str>) {
+        let mut simulator = simulator();
+
+        assert_eq!(
+            Some(Effect { changed: 1, matched: 1 }),
+            simulator.apply_command("items.xml", &command).unwrap()
+        );
+        assert_eq!(expected, attribute(&mut simulator, xpath));
+    }
+
+    #[test]
+    fn test_union() {
+        let mut simulator = simulator();
+        let command = Command::Set(instruction_set(
+            "//item[@name='gunPistol']//@value | /items/item[2]/property/@value | //property[@name='Tags']/@value",
+            "7",
+        ));
+
+        assert_eq!(
+            Some(Effect { changed: 4, matched: 4 }),
+            simulator.apply_command("items.xml", &command).unwrap()
+        );
+        assert_eq!(vec!["7", "7", "7", "7"], attribute(&mut simulator, "//property/@value"));
+    }
+
+    #[test]
+    fn test_append() {
+        let mut simulator = simulator();
+        let command = Command::Append(InstructionSet {
+            xpath: b"/items".to_vec(),
+            values: vec![Event::Empty(BytesStart::from_content(r#"item name="myNewGun""#, 4))],
+            ..InstructionSet::new()
+        });
+
+        assert_eq!(
+            Some(1),
+            simulator
+                .apply_command("items.xml", &command)
+                .unwrap()
+                .map(|e| e.matched)
+        );
+        assert_eq!(
+            vec!["gunPistol", "ammo9mmBullet", "myNewGun"],
+            attribute(&mut simulator, "/items/item/@name")
+        );
+    }
+
+    #[rstest]
+    #[case::set(Command::Set(instruction_set("//item[@name='gunPistol']/property[@name='Stacknumber']/@value", "1")))]
+    #[case::set_attribute(Command::SetAttribute(InstructionSet {
+        attribute: Some(b"value".to_vec()),
+        ..instruction_set("//item[@name='ammo9mmBullet']/property", "300")
+    }))]
+    #[case::remove_attribute(Command::RemoveAttribute(instruction_set("//item/@missing", "")))]
+    #[case::csv_add(Command::Csv(InstructionSet { csv_op: Some(CsvInstruction::Add(',')), ..instruction_set("//property[@name='Tags']/@value", "ranged") }))]
+    #[case::csv_remove(Command::Csv(InstructionSet { csv_op: Some(CsvInstruction::Remove('|')), ..instruction_set("//property[@name='Groups']/@value", "sniper") }))]
+    fn test_noop(#[case] command: Command) {
+        let mut simulator = simulator();
+        let effect = simulator.apply_command("items.xml", &command).unwrap().unwrap();
+
+        assert!(effect.is_noop(), "{effect:?}");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut simulator = simulator();
+        let invalid_xpath = Command::Set(instruction_set("/items/item[@name=", "5"));
+        let missing_op = Command::Csv(instruction_set("//property[@name='Tags']/@value", "melee"));
+
+        assert!(matches!(
+            simulator.apply_command("items.xml", &invalid_xpath),
+            Err(ModletError::InvalidXPath { .. })
+        ));
+        assert!(matches!(
+            simulator.apply_command("items.xml", &missing_op),
+            Err(ModletError::MissingAttribute { attribute: "op", .. })
+        ));
+        assert!(matches!(
+            Document::parse("<items><item></items>"),
+            Err(ModletError::InvalidDocument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut simulator = simulator();
+        let mut modlet = Modlet::empty("Bundle");
+        modlet.add_command("items.xml", Command::Comment(" Included from ModA ".into(), None));
+        modlet.add_command(
+            "items.xml",
+            Command::Remove(instruction_set("/items/item[@name='ammo9mmBullet']", "")),
+        );
+        simulator.set_annotate(true);
+        simulator.apply(&modlet).unwrap();
+
+        let xml = simulator.document("items.xml").unwrap().to_xml().unwrap();
+
+        assert!(
+            xml.contains("<!-- ModA: remove /items/item[@name='ammo9mmBullet'] -->\n<items>"),
+            "{xml}"
+        );
+    }
+}
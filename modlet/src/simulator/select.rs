@@ -0,0 +1,236 @@
+/// This module evaluates parsed xpaths against a `Document`.
+use super::document::{Document, NodeId, NodeKind};
+use crate::xpath::{Axis, BinaryOp, Expr, LocationPath, NodeTest, Step};
+use std::collections::HashSet;
+
+/// A node (or attribute) selected by an xpath
+#[derive(Debug, Clone, Eq, Hash, PartialEq)]
+pub enum Selection {
+    Attribute(NodeId, String),
+    Node(NodeId),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Boolean(bool),
+    Nodes(Vec<Selection>),
+    Number(f64),
+    String(String),
+}
+
+/// Returns every node (or attribute) selected by `path`
+pub fn select(document: &Document, path: &LocationPath) -> Vec<Selection> {
+    evaluate_path(document, path, &Selection::Node(document.root()))
+}
+
+/// Returns every node (or attribute) selected by any of a union's `paths`, each once, in the order of the paths
+pub fn select_union(document: &Document, paths: &[LocationPath]) -> Vec<Selection> {
+    let mut seen = HashSet::new();
+
+    paths
+        .iter()
+        .flat_map(|path| select(document, path))
+        .filter(|selection| seen.insert(selection.clone()))
+        .collect()
+}
+
+fn evaluate_path(document: &Document, path: &LocationPath, context: &Selection) -> Vec<Selection> {
+    let mut current = if path.absolute {
+        vec![Selection::Node(document.root())]
+    } else {
+        vec![context.clone()]
+    };
+
+    for step in &path.steps {
+        let mut seen = HashSet::new();
+        let mut next = Vec::new();
+
+        for selection in &current {
+            let candidates = filter(document, step, candidates(document, step, selection));
+            for candidate in candidates {
+                if seen.insert(candidate.clone()) {
+                    next.push(candidate);
+                }
+            }
+        }
+
+        current = next;
+    }
+
+    current
+}
+
+fn candidates(document: &Document, step: &Step, selection: &Selection) -> Vec<Selection> {
+    let (id, is_attribute) = match selection {
+        Selection::Node(id) => (*id, false),
+        Selection::Attribute(id, _) => (*id, true),
+    };
+
+    match step.axis {
+        Axis::SelfNode => vec![selection.clone()],
+        Axis::Parent if is_attribute => vec![Selection::Node(id)],
+        Axis::Parent => document.parent(id).map(Selection::Node).into_iter().collect(),
+        _ if is_attribute => Vec::new(),
+        Axis::Attribute => document
+            .attributes(id)
+            .iter()
+            .filter(|(key, _)| match &step.test {
+                NodeTest::Name(name) => key == name,
+                NodeTest::Wildcard | NodeTest::Node => true,
+                NodeTest::Text => false,
+            })
+            .map(|(key, _)| Selection::Attribute(id, key.clone()))
+            .collect(),
+        Axis::Child => document
+            .children(id)
+            .iter()
+            .copied()
+            .filter(|&child| matches(document, &step.test, child))
+            .map(Selection::Node)
+            .collect(),
+        Axis::DescendantOrSelf => std::iter::once(id)
+            .chain(document.descendants(id))
+            .filter(|&child| matches(document, &step.test, child))
+            .map(Selection::Node)
+            .collect(),
+    }
+}
+
+fn matches(document: &Document, test: &NodeTest, id: NodeId) -> bool {
+    match (test, document.kind(id)) {
+        (NodeTest::Node, _) => true,
+        (NodeTest::Text, NodeKind::Text(_)) => true,
+        (NodeTest::Wildcard, NodeKind::Element { .. }) => true,
+        (NodeTest::Name(test), NodeKind::Element { name, .. }) => test == name,
+        _ => false,
+    }
+}
+
+fn filter(document: &Document, step: &Step, mut selections: Vec<Selection>) -> Vec<Selection> {
+    for predicate in &step.predicates {
+        let size = selections.len();
+        selections = selections
+            .into_iter()
+            .enumerate()
+            .filter(|(index, selection)| {
+                let position = index + 1;
+                match evaluate(document, predicate, selection, position, size) {
+                    Value::Number(number) => number == position as f64,
+                    value => value.boolean(),
+                }
+            })
+            .map(|(_, selection)| selection)
+            .collect();
+    }
+
+    selections
+}
+
+fn evaluate(document: &Document, expr: &Expr, context: &Selection, position: usize, size: usize) -> Value {
+    let eval = |expr| evaluate(document, expr, context, position, size);
+
+    match expr {
+        Expr::Literal(literal) => Value::String(literal.clone()),
+        Expr::Number(number) => Value::Number(*number),
+        Expr::Path(path) => Value::Nodes(evaluate_path(document, path, context)),
+        Expr::Binary(lhs, BinaryOp::Or, rhs) => Value::Boolean(eval(lhs).boolean() || eval(rhs).boolean()),
+        Expr::Binary(lhs, BinaryOp::And, rhs) => Value::Boolean(eval(lhs).boolean() && eval(rhs).boolean()),
+        Expr::Binary(lhs, op, rhs) => Value::Boolean(compare(document, &eval(lhs), *op, &eval(rhs))),
+        Expr::Function(name, args) => {
+            let args: Vec<Value> = args.iter().map(eval).collect();
+            let string = |index: usize| {
+                args.get(index)
+                    .map(|arg| arg.string(document))
+                    .unwrap_or_else(|| string_value(document, context))
+            };
+
+            match name.as_str() {
+                "contains" => Value::Boolean(string(0).contains(&string(1))),
+                "starts-with" => Value::Boolean(string(0).starts_with(&string(1))),
+                "ends-with" => Value::Boolean(string(0).ends_with(&string(1))),
+                "not" => Value::Boolean(!args.first().map(Value::boolean).unwrap_or_default()),
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                "position" => Value::Number(position as f64),
+                "last" => Value::Number(size as f64),
+                "count" => Value::Number(match args.first() {
+                    Some(Value::Nodes(nodes)) => nodes.len() as f64,
+                    _ => 0.0,
+                }),
+                "string" => Value::String(string(0)),
+                "string-length" => Value::Number(string(0).chars().count() as f64),
+                "normalize-space" => Value::String(string(0).split_whitespace().collect::<Vec<_>>().join(" ")),
+                // Unknown functions never match anything
+                _ => Value::Boolean(false),
+            }
+        }
+    }
+}
+
+fn compare(document: &Document, lhs: &Value, op: BinaryOp, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (Value::Nodes(nodes), other) => nodes.iter().any(|node| {
+            let value = Value::String(string_value(document, node));
+            compare(document, &value, op, other)
+        }),
+        (other, Value::Nodes(nodes)) => nodes.iter().any(|node| {
+            let value = Value::String(string_value(document, node));
+            compare(document, other, op, &value)
+        }),
+        _ => match op {
+            BinaryOp::Eq | BinaryOp::NotEq => {
+                let equal = match (lhs, rhs) {
+                    (Value::Boolean(_), _) | (_, Value::Boolean(_)) => lhs.boolean() == rhs.boolean(),
+                    (Value::Number(_), _) | (_, Value::Number(_)) => lhs.number() == rhs.number(),
+                    _ => lhs.string(document) == rhs.string(document),
+                };
+                equal == (op == BinaryOp::Eq)
+            }
+            BinaryOp::Lt => lhs.number() < rhs.number(),
+            BinaryOp::LtEq => lhs.number() <= rhs.number(),
+            BinaryOp::Gt => lhs.number() > rhs.number(),
+            BinaryOp::GtEq => lhs.number() >= rhs.number(),
+            BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are evaluated in `evaluate`"),
+        },
+    }
+}
+
+/// Returns the text value of a selected node or attribute
+pub fn string_value(document: &Document, selection: &Selection) -> String {
+    match selection {
+        Selection::Node(id) => document.text(*id),
+        Selection::Attribute(id, name) => document.attribute(*id, name).unwrap_or_default().to_owned(),
+    }
+}
+
+impl Value {
+    fn boolean(&self) -> bool {
+        match self {
+            Value::Boolean(boolean) => *boolean,
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::Number(number) => *number != 0.0 && !number.is_nan(),
+            Value::String(string) => !string.is_empty(),
+        }
+    }
+
+    fn number(&self) -> f64 {
+        match self {
+            Value::Boolean(boolean) => f64::from(u8::from(*boolean)),
+            Value::Number(number) => *number,
+            Value::String(string) => string.trim().parse().unwrap_or(f64::NAN),
+            Value::Nodes(_) => f64::NAN,
+        }
+    }
+
+    fn string(&self, document: &Document) -> String {
+        match self {
+            Value::Boolean(boolean) => boolean.to_string(),
+            Value::Nodes(nodes) => nodes
+                .first()
+                .map(|node| string_value(document, node))
+                .unwrap_or_default(),
+            Value::Number(number) => number.to_string(),
+            Value::String(string) => string.clone(),
+        }
+    }
+}
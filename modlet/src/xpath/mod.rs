@@ -0,0 +1,667 @@
+/// This module contains a parser for the XPath dialect accepted by 7 Days to Die modlets.
+/// XPaths are parsed into a small AST (`LocationPath` / `Expr`) which can then be evaluated
+/// against an XML document, displayed in a canonical form, or queried for the elements it targets.
+/// A union of location paths (`/items/item/@a|/blocks/block/@b`) is parsed with `parse_union`.
+use crate::error::XPathError;
+use std::fmt::{self, Display, Formatter};
+
+/// A location path, such as `/items/item[@name='gunPistol']/@Tags`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocationPath {
+    pub absolute: bool,
+    pub steps: Vec<Step>,
+}
+
+/// A single step of a location path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub axis: Axis,
+    pub test: NodeTest,
+    pub predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Axis {
+    Attribute,
+    Child,
+    DescendantOrSelf,
+    Parent,
+    SelfNode,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NodeTest {
+    Name(String),
+    Node,
+    Text,
+    Wildcard,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BinaryOp {
+    And,
+    Eq,
+    Gt,
+    GtEq,
+    Lt,
+    LtEq,
+    NotEq,
+    Or,
+}
+
+/// An expression, as found inside a predicate
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+    Function(String, Vec<Expr>),
+    Literal(String),
+    Number(f64),
+    Path(LocationPath),
+}
+
+/// Parses an xpath into a `LocationPath`
+///
+/// # Errors
+///
+/// * If the xpath is not valid, reporting the (0-based, byte) position of the problem
+pub fn parse(input: &str) -> Result<LocationPath, XPathError> {
+    let mut parser = Parser::new(input)?;
+    let path = parser.location_path()?;
+    parser.expect_end()?;
+
+    Ok(path)
+}
+
+/// Parses an xpath which may be a union of location paths separated by `|`, returning each of them in order
+///
+/// # Errors
+///
+/// * If the xpath is not valid, reporting the (0-based, byte) position of the problem
+pub fn parse_union(input: &str) -> Result<Vec<LocationPath>, XPathError> {
+    let mut parser = Parser::new(input)?;
+    let mut paths = vec![parser.location_path()?];
+    while parser.peek() == Some(&Token::Pipe) {
+        parser.next();
+        paths.push(parser.location_path()?);
+    }
+    parser.expect_end()?;
+
+    Ok(paths)
+}
+
+impl LocationPath {
+    /// The attribute targeted by the final step (e.g. `Tags` for `/items/item/@Tags`), if any
+    pub fn target_attribute(&self) -> Option<&str> {
+        match self.steps.last() {
+            Some(Step {
+                axis: Axis::Attribute,
+                test: NodeTest::Name(name),
+                ..
+            }) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The elements this path filters by `@name`, as `(element, name)` pairs.
+    ///
+    /// `/items/item[@name='gunPistol']/property[@name='Tags']` returns
+    /// `[("item", "gunPistol"), ("property", "Tags")]`. A wildcard step's element is `*`.
+    pub fn named_elements(&self) -> Vec<(&str, &str)> {
+        self.steps
+            .iter()
+            .filter(|step| step.axis == Axis::Child)
+            .flat_map(|step| {
+                let element = match &step.test {
+                    NodeTest::Name(name) => name.as_str(),
+                    _ => "*",
+                };
+                step.predicates
+                    .iter()
+                    .flat_map(|predicate| predicate.name_equals())
+                    .map(move |name| (element, name))
+            })
+            .collect()
+    }
+
+    /// Returns `true` if this path filters `element` by the given `@name`
+    pub fn touches(&self, element: &str, name: &str) -> bool {
+        self.named_elements()
+            .iter()
+            .any(|&(e, n)| (e == element || e == "*") && n == name)
+    }
+
+    /// Returns `true` for the relative path `@name`
+    fn is_name_attribute(&self) -> bool {
+        !self.absolute && self.steps.len() == 1 && self.target_attribute() == Some("name")
+    }
+}
+
+impl Expr {
+    /// The values this expression requires `@name` to equal, looking through `and` (but not `or`)
+    fn name_equals(&self) -> Vec<&str> {
+        match self {
+            Expr::Binary(lhs, BinaryOp::And, rhs) => lhs.name_equals().into_iter().chain(rhs.name_equals()).collect(),
+            Expr::Binary(lhs, BinaryOp::Eq, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (Expr::Path(path), Expr::Literal(value)) | (Expr::Literal(value), Expr::Path(path))
+                    if path.is_name_attribute() =>
+                {
+                    vec![value.as_str()]
+                }
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> usize {
+        match self {
+            BinaryOp::Or => 0,
+            BinaryOp::And => 1,
+            BinaryOp::Eq | BinaryOp::NotEq => 2,
+            BinaryOp::Gt | BinaryOp::GtEq | BinaryOp::Lt | BinaryOp::LtEq => 3,
+        }
+    }
+}
+
+impl Display for LocationPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.absolute {
+            write!(f, "/")?;
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            // The `//` abbreviation is displayed as an empty step between two slashes
+            if *step != Step::descendant_or_self() {
+                write!(f, "{step}")?;
+            } else if i == 0 && !self.absolute {
+                write!(f, "/")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.axis, &self.test) {
+            (Axis::Attribute, test) => write!(f, "@{test}")?,
+            (Axis::Parent, _) => write!(f, "..")?,
+            (Axis::SelfNode, _) => write!(f, ".")?,
+            (_, test) => write!(f, "{test}")?,
+        }
+        for predicate in &self.predicates {
+            write!(f, "[{predicate}]")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for NodeTest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeTest::Name(name) => write!(f, "{name}"),
+            NodeTest::Node => write!(f, "node()"),
+            NodeTest::Text => write!(f, "text()"),
+            NodeTest::Wildcard => write!(f, "*"),
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::And => "and",
+            BinaryOp::Eq => "=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Or => "or",
+        };
+
+        write!(f, "{op}")
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Binary(lhs, op, rhs) => {
+                // Operators are left-associative, so only the right-hand side needs parentheses at equal precedence
+                let group = |expr: &Expr, right: bool| match expr {
+                    Expr::Binary(_, inner, _) if inner.precedence() < op.precedence() => true,
+                    Expr::Binary(_, inner, _) => right && inner.precedence() == op.precedence(),
+                    _ => false,
+                };
+                match group(lhs, false) {
+                    true => write!(f, "({lhs})")?,
+                    false => write!(f, "{lhs}")?,
+                }
+                // Comparisons are written tightly (`@name='x'`), as modders usually write them
+                match op {
+                    BinaryOp::And | BinaryOp::Or => write!(f, " {op} ")?,
+                    _ => write!(f, "{op}")?,
+                }
+                match group(rhs, true) {
+                    true => write!(f, "({rhs})"),
+                    false => write!(f, "{rhs}"),
+                }
+            }
+            Expr::Function(name, args) => {
+                write!(f, "{name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Expr::Literal(literal) if literal.contains('\'') => write!(f, "\"{literal}\""),
+            Expr::Literal(literal) => write!(f, "'{literal}'"),
+            Expr::Number(number) => write!(f, "{number}"),
+            Expr::Path(path) => write!(f, "{path}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    At,
+    Comma,
+    Dot,
+    DotDot,
+    DoubleSlash,
+    LBracket,
+    LParen,
+    Literal(String),
+    Name(String),
+    Number(f64),
+    Op(BinaryOp),
+    Pipe,
+    RBracket,
+    RParen,
+    Slash,
+    Star,
+}
+
+/// Splits an xpath into tokens, each paired with its position in the input
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, XPathError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        let token = match ch {
+            c if c.is_whitespace() => continue,
+            '@' => Token::At,
+            ',' => Token::Comma,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '*' => Token::Star,
+            '|' => Token::Pipe,
+            '=' => Token::Op(BinaryOp::Eq),
+            '/' => {
+                if chars.next_if(|&(_, c)| c == '/').is_some() {
+                    Token::DoubleSlash
+                } else {
+                    Token::Slash
+                }
+            }
+            '!' => {
+                if chars.next_if(|&(_, c)| c == '=').is_some() {
+                    Token::Op(BinaryOp::NotEq)
+                } else {
+                    return Err(XPathError::new(pos, "Unexpected character '!'"));
+                }
+            }
+            '<' | '>' => {
+                let or_equal = chars.next_if(|&(_, c)| c == '=').is_some();
+                match (ch, or_equal) {
+                    ('<', false) => Token::Op(BinaryOp::Lt),
+                    ('<', true) => Token::Op(BinaryOp::LtEq),
+                    (_, false) => Token::Op(BinaryOp::Gt),
+                    (_, true) => Token::Op(BinaryOp::GtEq),
+                }
+            }
+            '.' => {
+                if chars.next_if(|&(_, c)| c == '.').is_some() {
+                    Token::DotDot
+                } else {
+                    Token::Dot
+                }
+            }
+            '\'' | '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, c)) if c == ch => break,
+                        Some((_, c)) => literal.push(c),
+                        None => return Err(XPathError::new(pos, "Unterminated string literal")),
+                    }
+                }
+                Token::Literal(literal)
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_digit() || c == '.') {
+                    number.push(c);
+                }
+                Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| XPathError::new(pos, format!("Invalid number '{number}'")))?,
+                )
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some((_, c)) =
+                    chars.next_if(|&(_, c)| c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ':')
+                {
+                    name.push(c);
+                }
+                Token::Name(name)
+            }
+            c => return Err(XPathError::new(pos, format!("Unexpected character '{c}'"))),
+        };
+
+        tokens.push((pos, token));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    /// The length of the input, reported as the position of errors at the end of the xpath
+    len: usize,
+    pos: usize,
+    tokens: Vec<(usize, Token)>,
+}
+
+impl Parser {
+    fn new(input: &str) -> Result<Self, XPathError> {
+        Ok(Self {
+            len: input.len(),
+            pos: 0,
+            tokens: tokenize(input)?,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Builds an error located at the most recently consumed token (or the end of the xpath)
+    fn error(&self, message: impl Into<String>) -> XPathError {
+        let position = self
+            .tokens
+            .get(self.pos.saturating_sub(1))
+            .map_or(self.len, |(position, _)| *position);
+
+        XPathError::new(position, message)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), XPathError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(self.error(format!("Expected {expected:?}, found {token:?}"))),
+            None => Err(self.error(format!("Expected {expected:?}, found end of xpath"))),
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), XPathError> {
+        match self.next() {
+            Some(token) => Err(self.error(format!("Unexpected {token:?} after end of xpath"))),
+            None => Ok(()),
+        }
+    }
+
+    fn at_step_start(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::At | Token::Dot | Token::DotDot | Token::Name(_) | Token::Star)
+        )
+    }
+
+    fn location_path(&mut self) -> Result<LocationPath, XPathError> {
+        let mut steps = Vec::new();
+        let absolute = matches!(self.peek(), Some(Token::Slash | Token::DoubleSlash));
+
+        match self.peek() {
+            Some(Token::Slash) => {
+                self.next();
+                // A lone `/` selects the document itself
+                if !self.at_step_start() {
+                    return Ok(LocationPath { absolute, steps });
+                }
+            }
+            Some(Token::DoubleSlash) => {
+                self.next();
+                steps.push(Step::descendant_or_self());
+            }
+            _ => (),
+        }
+
+        steps.push(self.step()?);
+
+        loop {
+            match self.peek() {
+                Some(Token::Slash) => {
+                    self.next();
+                }
+                Some(Token::DoubleSlash) => {
+                    self.next();
+                    steps.push(Step::descendant_or_self());
+                }
+                _ => break,
+            }
+            steps.push(self.step()?);
+        }
+
+        Ok(LocationPath { absolute, steps })
+    }
+
+    fn step(&mut self) -> Result<Step, XPathError> {
+        let (axis, test) = match self.next() {
+            Some(Token::Dot) => (Axis::SelfNode, NodeTest::Node),
+            Some(Token::DotDot) => (Axis::Parent, NodeTest::Node),
+            Some(Token::Star) => (Axis::Child, NodeTest::Wildcard),
+            Some(Token::At) => match self.next() {
+                Some(Token::Name(name)) => (Axis::Attribute, NodeTest::Name(name)),
+                Some(Token::Star) => (Axis::Attribute, NodeTest::Wildcard),
+                Some(token) => return Err(self.error(format!("Expected attribute name, found {token:?}"))),
+                None => return Err(self.error("Expected attribute name, found end of xpath")),
+            },
+            Some(Token::Name(name)) if self.peek() == Some(&Token::LParen) => {
+                let test = match name.as_str() {
+                    "text" => NodeTest::Text,
+                    "node" => NodeTest::Node,
+                    _ => return Err(self.error(format!("Unexpected function {name}() in location path"))),
+                };
+                self.expect(Token::LParen)?;
+                self.expect(Token::RParen)?;
+                (Axis::Child, test)
+            }
+            Some(Token::Name(name)) => (Axis::Child, NodeTest::Name(name)),
+            Some(token) => return Err(self.error(format!("Expected a location step, found {token:?}"))),
+            None => return Err(self.error("Expected a location step, found end of xpath")),
+        };
+
+        let mut predicates = Vec::new();
+        while self.peek() == Some(&Token::LBracket) {
+            self.next();
+            predicates.push(self.expr()?);
+            self.expect(Token::RBracket)?;
+        }
+
+        Ok(Step { axis, test, predicates })
+    }
+
+    fn expr(&mut self) -> Result<Expr, XPathError> {
+        self.binary(0)
+    }
+
+    /// Parses binary operators by precedence level: or, and, equality, relational
+    fn binary(&mut self, level: usize) -> Result<Expr, XPathError> {
+        const LEVELS: [&[BinaryOp]; 4] = [
+            &[BinaryOp::Or],
+            &[BinaryOp::And],
+            &[BinaryOp::Eq, BinaryOp::NotEq],
+            &[BinaryOp::Lt, BinaryOp::LtEq, BinaryOp::Gt, BinaryOp::GtEq],
+        ];
+
+        if level == LEVELS.len() {
+            return self.primary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) => *op,
+                Some(Token::Name(name)) if name == "or" => BinaryOp::Or,
+                Some(Token::Name(name)) if name == "and" => BinaryOp::And,
+                _ => break,
+            };
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.next();
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn primary(&mut self) -> Result<Expr, XPathError> {
+        match self.peek() {
+            Some(Token::Literal(literal)) => {
+                let literal = literal.clone();
+                self.next();
+                Ok(Expr::Literal(literal))
+            }
+            Some(Token::Number(number)) => {
+                let number = *number;
+                self.next();
+                Ok(Expr::Number(number))
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) if self.peek_at(1) == Some(&Token::LParen) && name != "text" && name != "node" => {
+                let name = name.clone();
+                self.next();
+                self.next();
+
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    args.push(self.expr()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.next();
+                        args.push(self.expr()?);
+                    }
+                }
+                self.expect(Token::RParen)?;
+
+                Ok(Expr::Function(name, args))
+            }
+            Some(_) => Ok(Expr::Path(self.location_path()?)),
+            None => Err(XPathError::new(self.len, "Expected an expression, found end of xpath")),
+        }
+    }
+}
+
+impl Step {
+    /// The step inserted for the `//` abbreviation
+    fn descendant_or_self() -> Self {
+        Self {
+            axis: Axis::DescendantOrSelf,
+            test: NodeTest::Node,
+            predicates: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rstest]
+    #[case::absolute("/items/item[@name='gunPistol']/@Tags")]
+    #[case::descendant("//property[@name='Tags']/@value")]
+    #[case::nested_descendant("/items//property[starts-with(@name, 'Magazine')]")]
+    #[case::and_or("/items/item[@name='a' and (@name='b' or contains(@Tags, \"it's\"))]")]
+    #[case::position("/blocks/block[3]/property[last()]")]
+    #[case::wildcard("/*/item/@*")]
+    #[case::parent("//item[@name='a']/../text()")]
+    fn test_display_round_trip(#[case] input: &str) {
+        assert_eq!(input, parse(input).unwrap().to_string());
+    }
+
+    #[rstest]
+    #[case::single("/items/item/@Tags", vec!["/items/item/@Tags"])]
+    #[case::union("/items/item/@Tags|//block[@name='a']/@value", vec!["/items/item/@Tags", "//block[@name='a']/@value"])]
+    #[case::whitespace("/a/@x | /b/@y", vec!["/a/@x", "/b/@y"])]
+    fn test_parse_union(#[case] input: &str, #[case] expected: Vec<&str>) {
+        let paths = parse_union(input).unwrap();
+
+        assert_eq!(expected, paths.iter().map(ToString::to_string).collect::<Vec<_>>());
+    }
+
+    #[rstest]
+    #[case::leading_pipe("|/a/@x", 0)]
+    #[case::trailing_pipe("/a/@x|", 6)]
+    #[case::in_predicate("/a[@x|@y]", 5)]
+    fn test_union_error_position(#[case] input: &str, #[case] position: usize) {
+        assert_eq!(position, parse_union(input).unwrap_err().position);
+    }
+
+    #[test]
+    fn test_single_path_rejects_union() {
+        assert_eq!(5, parse("/a/@x|/b/@y").unwrap_err().position);
+    }
+
+    #[rstest]
+    #[case::unexpected_character("/items/item[#]", 12)]
+    #[case::unterminated_literal("/items/item[@name='gun]", 18)]
+    #[case::unclosed_predicate("/items/item[@name='gun'", 23)]
+    #[case::missing_attribute_name("/items/item/@", 13)]
+    #[case::trailing_token("/items/item]", 11)]
+    fn test_error_position(#[case] input: &str, #[case] position: usize) {
+        assert_eq!(position, parse(input).unwrap_err().position);
+    }
+
+    #[test]
+    fn test_named_elements() {
+        let path = parse("/items/item[@name='gunPistol']/property[@class='Action0' and @name='Delay']/@value").unwrap();
+
+        assert_eq!(
+            vec![("item", "gunPistol"), ("property", "Delay")],
+            path.named_elements()
+        );
+        assert_eq!(Some("value"), path.target_attribute());
+        assert!(path.touches("item", "gunPistol"));
+        assert!(!path.touches("block", "gunPistol"));
+    }
+}
@@ -3,3 +3,5 @@ pub use cli::SETTINGS;
 
 pub mod commands;
 pub mod helpers;
+#[cfg(test)]
+pub mod test_helpers;
@@ -3,8 +3,16 @@ use crate::dmt::helpers::verify_modlet_paths;
 use crate::CommandResult;
 use clap::{Args, Parser, Subcommand};
 use lazy_static::lazy_static;
+use modlet::{
+    lint::{Rule, Severity},
+    modlet::AssetRules,
+};
 use serde::{Deserialize, Serialize};
-use std::{fmt, path::PathBuf, sync::RwLock};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
 use thiserror::Error;
 
 #[derive(Debug, Parser)]
@@ -27,6 +35,16 @@ pub struct Cli {
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    /// Zip a modlet as <Name>-<Version>.zip, ready to unpack into the game's Mods folder
+    #[command(arg_required_else_help = true)]
+    Archive {
+        /// The modlet path to archive
+        path: PathBuf,
+
+        /// The directory to write the archive to (default: beside the modlet)
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
     /// Bump the version of a modlet
     #[command(arg_required_else_help = true)]
     Bump {
@@ -47,6 +65,48 @@ pub enum Commands {
         #[command(flatten)]
         requested_version: Option<RequestedVersion>,
     },
+    /// Generate a modlet from the differences between a vanilla config file and an edited copy of it
+    #[command(arg_required_else_help = true)]
+    DiffToModlet {
+        /// The vanilla config file (e.g. Data/Config/items.xml)
+        #[arg(long, value_name = "FILE")]
+        vanilla: PathBuf,
+
+        /// The edited copy of the vanilla config file
+        #[arg(long, value_name = "FILE")]
+        edited: PathBuf,
+
+        /// The modlet to write the commands to
+        #[arg(short, long, value_name = "MODLET")]
+        output: PathBuf,
+    },
+    /// Dump a modlet's parsed commands as JSON or YAML
+    #[command(arg_required_else_help = true)]
+    Dump {
+        /// The modlet path to dump
+        path: PathBuf,
+
+        /// [Optionally] the output format (default: JSON)
+        #[command(flatten)]
+        format: Option<DumpFormat>,
+    },
+    /// Describe a modlet's changes in plain language, grouped by config file
+    #[command(arg_required_else_help = true)]
+    Explain {
+        /// The modlet path to explain
+        path: PathBuf,
+    },
+    /// Rewrite a modlet's XML files in the canonical format
+    #[command(arg_required_else_help = true)]
+    Fmt {
+        /// Report the files which are not formatted (exiting with an error), without changing them
+        #[arg(long)]
+        check: bool,
+
+        /// The modlet path(s) to format
+        #[arg(value_name = "MODLET_PATHS", required = true)]
+        modlets: Vec<PathBuf>,
+    },
     /// Initialize a new modlet
     #[command(arg_required_else_help = true)]
     Init {
@@ -57,15 +117,98 @@ pub enum Commands {
         #[command(flatten)]
         requested_version: Option<RequestedVersion>,
     },
+    /// Check modlet(s) for common mistakes, such as unknown commands and misquoted xpaths
+    #[command(arg_required_else_help = true)]
+    Lint {
+        /// Don't check a rule (may be repeated)
+        #[arg(long, value_name = "RULE")]
+        allow: Vec<Rule>,
+
+        /// Report a rule as an error (may be repeated)
+        #[arg(long, value_name = "RULE")]
+        deny: Vec<Rule>,
+
+        /// Report a rule as a warning (may be repeated)
+        #[arg(long, value_name = "RULE")]
+        warn: Vec<Rule>,
+
+        /// The modlet path(s) to lint
+        #[arg(value_name = "MODLET_PATHS", required = true)]
+        modlets: Vec<PathBuf>,
+    },
+    /// Map the XML patch warnings and errors in a game log back to the modlet commands which caused them
+    #[command(arg_required_else_help = true)]
+    Logcheck {
+        /// The game's player or server log (e.g. output_log.txt)
+        log: PathBuf,
+
+        /// The modlet (or packaged bundle) path(s) the game loaded
+        #[arg(value_name = "MODLET_PATHS")]
+        modlets: Vec<PathBuf>,
+    },
     // Future: We'll process instructions in special `dmt` xml sections to create
     // larger modlets -- ala lessgrind.
     /// Package Modlet(s)
     #[command(arg_required_else_help = true)]
     Package {
         /// The modlet to package into
-        #[arg(short, long, value_name = "MODLET")]
+        #[arg(short, long, value_name = "MODLET", required_unless_present_any = ["bundle", "all"])]
+        output: Option<PathBuf>,
+
+        /// The modlet path(s) to operate on
+        #[arg(value_name = "MODLET_PATHS", required_unless_present_any = ["bundle", "all"])]
+        modlets: Vec<PathBuf>,
+
+        /// Fail if two or more modlets modify the same xpath
+        #[arg(long)]
+        deny_conflicts: bool,
+
+        /// A file listing modlets (one per line) in the order they should be loaded
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["bundle", "all"])]
+        order: Option<PathBuf>,
+
+        /// Build the named bundle(s) from the manifest (may be repeated)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["output", "modlets", "all"])]
+        bundle: Vec<String>,
+
+        /// Build every bundle in the manifest
+        #[arg(long, conflicts_with_all = ["output", "modlets"])]
+        all: bool,
+
+        /// The bundle manifest
+        #[arg(long, value_name = "FILE", default_value = commands::package::DEFAULT_MANIFEST)]
+        manifest: PathBuf,
+
+        /// Also zip each packaged modlet as <Name>-<Version>.zip, beside it
+        #[arg(long)]
+        archive: bool,
+
+        /// Only package files matching this glob, relative to each modlet (may be repeated)
+        #[arg(long, value_name = "GLOB", conflicts_with_all = ["bundle", "all"])]
+        include: Vec<String>,
+
+        /// Don't package files matching this glob, relative to each modlet (may be repeated)
+        #[arg(long, value_name = "GLOB", conflicts_with_all = ["bundle", "all"])]
+        exclude: Vec<String>,
+    },
+    /// Render the vanilla game configs with modlet(s) applied
+    #[command(arg_required_else_help = true)]
+    Render {
+        /// The directory to write the rendered configs to
+        #[arg(short, long, value_name = "DIR")]
         output: PathBuf,
 
+        /// The modlet (or packaged bundle) path(s) to apply
+        #[arg(value_name = "MODLET_PATHS", required = true)]
+        modlets: Vec<PathBuf>,
+
+        /// Precede each changed node with a comment naming the modlet and command which changed it
+        #[arg(long)]
+        annotate: bool,
+    },
+    /// Validate Modlet(s) against the vanilla game configs
+    #[command(arg_required_else_help = true)]
+    Validate {
         /// The modlet path(s) to operate on
         #[arg(value_name = "MODLET_PATHS", required = true)]
         modlets: Vec<PathBuf>,
@@ -75,10 +218,19 @@ pub enum Commands {
 impl fmt::Display for Commands {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Commands::Archive { .. } => write!(f, "Archive"),
             Commands::Bump { .. } => write!(f, "Bump"),
             Commands::Convert { .. } => write!(f, "Convert"),
+            Commands::DiffToModlet { .. } => write!(f, "DiffToModlet"),
+            Commands::Dump { .. } => write!(f, "Dump"),
+            Commands::Explain { .. } => write!(f, "Explain"),
+            Commands::Fmt { .. } => write!(f, "Fmt"),
             Commands::Init { .. } => write!(f, "Init"),
+            Commands::Lint { .. } => write!(f, "Lint"),
+            Commands::Logcheck { .. } => write!(f, "Logcheck"),
             Commands::Package { .. } => write!(f, "Package"),
+            Commands::Render { .. } => write!(f, "Render"),
+            Commands::Validate { .. } => write!(f, "Validate"),
         }
     }
 }
@@ -114,6 +266,17 @@ pub struct RequestedVersion {
     pub v2: bool,
 }
 
+#[derive(Args, Debug)]
+#[group(required = false, multiple = false)]
+pub struct DumpFormat {
+    /// Output JSON (default)
+    #[arg(long)]
+    pub json: bool,
+    /// Output YAML
+    #[arg(long)]
+    pub yaml: bool,
+}
+
 #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Config {
     #[serde(default)]
@@ -129,12 +292,22 @@ lazy_static! {
 pub enum CliError {
     #[error("Invalid argument: {0}")]
     InvalidArg(String),
-    // #[error("No game directory specified")]
-    // NoGameDirectory,
+    #[error("No game directory specified")]
+    NoGameDirectory,
     #[error("No modlet path specified")]
     NoModletPath,
     #[error("Unknown error: {0}")]
     Unknown(String),
+    #[error("{0}")]
+    Validation(String),
+}
+
+/// The directory containing a modlet, where its archive is written by default
+fn archive_directory(modlet: &Path) -> PathBuf {
+    match modlet.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
 }
 
 pub fn run() -> eyre::Result<CommandResult> {
@@ -145,6 +318,11 @@ pub fn run() -> eyre::Result<CommandResult> {
     SETTINGS.write().unwrap().verbosity = cli.verbose;
 
     match &cli.command {
+        Commands::Archive { path, output } => {
+            let output = output.clone().unwrap_or_else(|| archive_directory(path));
+            let archive = commands::archive::run(path, &output)?;
+            result.messages.push(format!("Wrote {}", archive.display()));
+        }
         Commands::Bump { paths, vers } => {
             if paths.is_empty() {
                 result.errors.push(CliError::NoModletPath);
@@ -192,6 +370,38 @@ pub fn run() -> eyre::Result<CommandResult> {
                 }
             }
         }
+        Commands::DiffToModlet {
+            vanilla,
+            edited,
+            output,
+        } => {
+            let (file, count) = commands::diff_to_modlet::run(vanilla, edited, output)?;
+            result
+                .messages
+                .push(format!("Wrote {count} command(s) to {}", file.display()));
+        }
+        Commands::Dump { path, format } => commands::dump::run(path, format.as_ref())?,
+        Commands::Explain { path } => commands::explain::run(path)?,
+        Commands::Fmt { check, modlets } => {
+            let verified_paths = verify_modlet_paths(modlets)?;
+            let outcomes = commands::fmt::run(&verified_paths, *check)?;
+
+            for outcome in outcomes {
+                let path = outcome.path.display();
+                match outcome.status {
+                    commands::fmt::Status::Changed if *check => {
+                        result
+                            .errors
+                            .push(CliError::Validation(format!("{path} is not formatted")));
+                    }
+                    commands::fmt::Status::Changed => result.messages.push(format!("Formatted {path}")),
+                    commands::fmt::Status::Failed(err) => {
+                        result.errors.push(CliError::Validation(format!("{path}: {err}")));
+                    }
+                    commands::fmt::Status::Unchanged => (),
+                }
+            }
+        }
         Commands::Init {
             name,
             requested_version,
@@ -208,15 +418,160 @@ pub fn run() -> eyre::Result<CommandResult> {
                 }
             }
         }
-        Commands::Package { modlets, output } => {
+        Commands::Lint {
+            allow,
+            deny,
+            warn,
+            modlets,
+        } => {
+            let verified_paths = verify_modlet_paths(modlets)?;
+            let opts = commands::lint::LintOptions {
+                allow: allow.clone(),
+                deny: deny.clone(),
+                game_directory: SETTINGS.read().unwrap().game_directory.clone(),
+                warn: warn.clone(),
+            };
+            let diagnostics = commands::lint::run(&verified_paths, &opts)?;
+
+            if diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.severity != Severity::Error)
+            {
+                result
+                    .messages
+                    .push(format!("{} modlet(s) linted", verified_paths.len()));
+            }
+            for diagnostic in diagnostics {
+                match diagnostic.severity {
+                    Severity::Error => result.errors.push(CliError::Validation(diagnostic.to_string())),
+                    _ => result.warnings.push(diagnostic.to_string()),
+                }
+            }
+        }
+        Commands::Logcheck { log, modlets } => {
+            let verified_paths = if modlets.is_empty() {
+                Vec::new()
+            } else {
+                verify_modlet_paths(modlets)?
+            };
+            let issues = commands::logcheck::run(log, &verified_paths)?;
+
+            if issues.is_empty() {
+                result
+                    .messages
+                    .push(format!("No XML patch problems found in {}", log.display()));
+            }
+            for (issue, source) in issues {
+                let error = match source {
+                    Some(source) => format!("{issue}\n    at {source}"),
+                    None => issue.to_string(),
+                };
+                result.errors.push(CliError::Validation(error));
+            }
+        }
+        Commands::Package {
+            modlets,
+            output,
+            deny_conflicts,
+            order,
+            bundle,
+            all,
+            manifest,
+            archive,
+            include,
+            exclude,
+        } => {
             // if SETTINGS.read().unwrap().game_directory.is_none() {
             //     result.errors.push(CliError::NoGameDirectory);
             // }
-            if modlets.is_empty() {
+            let game_directory = SETTINGS.read().unwrap().game_directory.clone();
+            if *all || !bundle.is_empty() {
+                let manifest = commands::package::Manifest::load(manifest)?;
+                for (name, bundle) in manifest.select(bundle)? {
+                    let verified_paths = verify_modlet_paths(&manifest.modlets(bundle)?)?;
+                    let opts = commands::package::PackageOptions {
+                        deny_conflicts: bundle.deny_conflicts || *deny_conflicts,
+                        files: bundle.files.clone(),
+                        game_directory: game_directory.clone(),
+                        modinfo: bundle.modinfo.clone(),
+                        order: bundle.order.as_ref().map(|order| manifest.path(order)),
+                    };
+                    let output = manifest.path(&bundle.output);
+                    commands::package::run(&verified_paths, &output, &opts)?;
+                    result.messages.push(format!("Packaged bundle {name}"));
+                    if *archive {
+                        let archive = commands::archive::run(&output, &archive_directory(&output))?;
+                        result.messages.push(format!("Wrote {}", archive.display()));
+                    }
+                }
+            } else if modlets.is_empty() {
                 result.errors.push(CliError::NoModletPath);
-            } else {
+            } else if let Some(output) = output {
                 let verified_paths = verify_modlet_paths(modlets)?;
-                commands::package::run(&verified_paths, output)?
+                let mut files = AssetRules::new();
+                for pattern in include {
+                    files = files.include(pattern)?;
+                }
+                for pattern in exclude {
+                    files = files.exclude(pattern)?;
+                }
+                let opts = commands::package::PackageOptions {
+                    deny_conflicts: *deny_conflicts,
+                    files,
+                    game_directory,
+                    order: order.clone(),
+                    ..Default::default()
+                };
+                commands::package::run(&verified_paths, output, &opts)?;
+                if *archive {
+                    let archive = commands::archive::run(output, &archive_directory(output))?;
+                    result.messages.push(format!("Wrote {}", archive.display()));
+                }
+            }
+        }
+        Commands::Render {
+            output,
+            modlets,
+            annotate,
+        } => {
+            let game_directory = SETTINGS.read().unwrap().game_directory.clone();
+            match game_directory {
+                None => result.errors.push(CliError::NoGameDirectory),
+                Some(game_directory) => {
+                    let verified_paths = verify_modlet_paths(modlets)?;
+                    let opts = commands::render::RenderOptions { annotate: *annotate };
+                    let written = commands::render::run(&verified_paths, &game_directory, output, &opts)?;
+
+                    result.messages.push(format!(
+                        "Rendered {} config(s) with {} modlet(s) to {}",
+                        written.len(),
+                        verified_paths.len(),
+                        output.display()
+                    ));
+                }
+            }
+        }
+        Commands::Validate { modlets } => {
+            let game_directory = SETTINGS.read().unwrap().game_directory.clone();
+            match game_directory {
+                None => result.errors.push(CliError::NoGameDirectory),
+                Some(game_directory) => {
+                    let verified_paths = verify_modlet_paths(modlets)?;
+                    let dead_patches = commands::validate::run(&verified_paths, &game_directory)?;
+
+                    if dead_patches.iter().all(|dead_patch| dead_patch.is_warning()) {
+                        result
+                            .messages
+                            .push(format!("{} modlet(s) validated", verified_paths.len()));
+                    }
+                    for dead_patch in dead_patches {
+                        if dead_patch.is_warning() {
+                            result.warnings.push(dead_patch.to_string());
+                        } else {
+                            result.errors.push(CliError::Validation(dead_patch.to_string()));
+                        }
+                    }
+                }
             }
         }
     };
@@ -0,0 +1,143 @@
+use color_eyre::eyre::eyre;
+use glob::glob;
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// The name the game looks for, which is case sensitive on Linux servers
+const MODINFO: &str = "ModInfo.xml";
+
+/// Finds a modlet's ModInfo.xml, however it is cased
+fn find_modinfo(modlet: &Path) -> Option<PathBuf> {
+    fs::read_dir(modlet)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| path.is_file() && path.file_name().is_some_and(|name| name.eq_ignore_ascii_case(MODINFO)))
+}
+
+/// Zips a modlet into `<Name>-<Version>.zip`, using the name and version from its ModInfo.xml.
+/// Everything is placed in a single `<Name>/` folder, so that players can unpack the archive straight into
+/// `Mods/`. Hidden files and folders (e.g. `.git`, `Config/.keep`) are left out.
+///
+/// # Arguments
+///
+/// * `modlet` - The modlet to archive
+/// * `output` - The directory to write the archive to
+///
+/// # Errors
+///
+/// * If the modlet has no ModInfo.xml, or it cannot be parsed
+/// * If the archive cannot be written
+///
+/// Returns the path of the archive written
+pub fn run(modlet: &Path, output: &Path) -> eyre::Result<PathBuf> {
+    let modinfo_path = find_modinfo(modlet).ok_or_else(|| eyre!("{} has no {MODINFO}", modlet.display()))?;
+    let modinfo = modinfo::parse(&modinfo_path)?;
+    let folder_name = modlet.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let name = modinfo
+        .get_value_for("name")
+        .filter(|name| !name.trim().is_empty())
+        .cloned()
+        .unwrap_or(folder_name);
+    let archive_path = output.join(format!("{name}-{}.zip", modinfo.get_version()));
+
+    let mut files = glob(&modlet.join("**/*").to_string_lossy())?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let relative = path.strip_prefix(modlet).ok()?.to_path_buf();
+            // The ModInfo.xml is always written first, under its canonical name
+            if relative.as_os_str().eq_ignore_ascii_case(MODINFO) {
+                return None;
+            }
+            let hidden = relative
+                .iter()
+                .any(|component| component.to_string_lossy().starts_with('.'));
+            (!hidden && relative.extension() != Some("zip".as_ref())).then_some((path, relative))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    fs::create_dir_all(output)?;
+    let mut zip = ZipWriter::new(File::create(&archive_path)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file(format!("{name}/{MODINFO}"), options)?;
+    zip.write_all(&fs::read(&modinfo_path)?)?;
+    for (path, relative) in files {
+        // Zip entries always use forward slashes
+        let entry = relative
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(format!("{name}/{entry}"), options)?;
+        io::copy(&mut File::open(path)?, &mut zip)?;
+    }
+    zip.finish()?;
+
+    Ok(archive_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmt::test_helpers::TempDir;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    const MODINFO_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xml>
+  <Name value="MyModlet" />
+  <DisplayName value="My Modlet" />
+  <Description value="A modlet" />
+  <Author value="Author" />
+  <Version value="1.0.0" />
+</xml>
+"#;
+
+    #[test]
+    fn test_run() {
+        let dir = TempDir::new();
+        let modlet = dir.join("MyModlet");
+        for (file, contents) in [
+            ("modinfo.xml", MODINFO_XML),
+            ("Config/items.xml", "<config/>"),
+            ("Config/.keep", ""),
+            (".git/HEAD", ""),
+            ("MyModlet-0.9.0.zip", ""),
+            ("UIAtlases/ItemIconAtlas/icon.png", ""),
+        ] {
+            let path = modlet.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let archive_path = run(&modlet, &dir.join("Out")).unwrap();
+        let mut archive = ZipArchive::new(File::open(archive_path).unwrap()).unwrap();
+        let entries: Vec<String> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect();
+
+        assert_eq!(
+            vec![
+                "MyModlet/ModInfo.xml",
+                "MyModlet/Config/items.xml",
+                "MyModlet/UIAtlases/ItemIconAtlas/icon.png",
+            ],
+            entries
+        );
+
+        let mut modinfo = String::new();
+        archive
+            .by_name("MyModlet/ModInfo.xml")
+            .unwrap()
+            .read_to_string(&mut modinfo)
+            .unwrap();
+        assert_eq!(MODINFO_XML, modinfo);
+    }
+}
@@ -0,0 +1,50 @@
+use modinfo::Modinfo;
+use modlet::{
+    modlet::Modlet,
+    simulator::{self, Document},
+};
+use std::path::{Path, PathBuf};
+
+/// Generates a modlet whose commands turn a vanilla config file into an edited copy of it
+///
+/// # Arguments
+///
+/// * `vanilla` - The vanilla config file (e.g. `Data/Config/items.xml`)
+/// * `edited` - The edited copy of the vanilla file
+/// * `output` - The modlet to write to. Its commands for the same file are replaced, and a ModInfo.xml is created
+///   if it doesn't exist
+///
+/// # Errors
+///
+/// * If either file cannot be loaded, or their root elements differ
+/// * If the modlet cannot be loaded or written
+///
+/// Returns the path of the written file, and the number of commands it contains
+pub fn run(vanilla: &Path, edited: &Path, output: &Path) -> eyre::Result<(PathBuf, usize)> {
+    let commands = simulator::diff(&Document::load(vanilla)?, &Document::load(edited)?)?;
+    let filename = Path::new(vanilla.file_name().unwrap_or_default());
+    let mut modlet = if output.join("Config").is_dir() {
+        Modlet::new(output)?
+    } else {
+        Modlet::empty(output)
+    };
+    let count = commands.len();
+
+    modlet.remove_commands(filename, |_| true);
+    for command in commands {
+        modlet.add_command(filename, command);
+    }
+    modlet.save()?;
+
+    let modinfo_path = output.join("ModInfo.xml");
+    if !modinfo_path.exists() {
+        let name = modlet.name();
+        let mut modinfo = Modinfo::new();
+        modinfo.set_modinfo_version(modinfo::ModinfoVersion::V2);
+        modinfo.set_value_for("name", &name);
+        modinfo.set_value_for("display_name", &name);
+        modinfo.write(Some(&modinfo_path))?;
+    }
+
+    Ok((output.join("Config").join(filename), count))
+}
@@ -0,0 +1,28 @@
+use crate::cli::DumpFormat;
+use console::Term;
+use modlet::modlet::Modlet;
+use std::path::Path;
+
+/// Serializes a parsed modlet (its commands, files and source spans) as JSON or YAML
+///
+/// # Arguments
+///
+/// * `path` - The modlet to dump
+/// * `format` - The output format (default: JSON)
+///
+/// # Errors
+///
+/// * If the modlet cannot be loaded
+/// * If the output cannot be written
+///
+pub fn run(path: &Path, format: Option<&DumpFormat>) -> eyre::Result<()> {
+    let modlet = Modlet::new(path)?;
+    let output = match format {
+        Some(format) if format.yaml => serde_yaml::to_string(&modlet)?,
+        _ => serde_json::to_string_pretty(&modlet)?,
+    };
+
+    Term::stdout().write_line(output.trim_end())?;
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use console::Term;
+use modlet::modlet::Modlet;
+use std::path::Path;
+
+/// Describes each of a modlet's commands in plain language, grouped by config file
+///
+/// # Arguments
+///
+/// * `path` - The modlet to explain
+///
+/// # Errors
+///
+/// * If the modlet cannot be loaded
+/// * If the output cannot be written
+///
+pub fn run(path: &Path) -> eyre::Result<()> {
+    let modlet = Modlet::new(path)?;
+    let term = Term::stdout();
+
+    for xml in &modlet.xmls {
+        let lines: Vec<String> = xml.commands.iter().flat_map(|command| command.explain()).collect();
+        if lines.is_empty() {
+            continue;
+        }
+
+        term.write_line(&format!("{}:", xml.filename().display()))?;
+        for line in lines {
+            // Commands within a conditional are indented beneath it
+            let description = line.trim_start();
+            let indent = &line[..line.len() - description.len()];
+            term.write_line(&format!("  {indent}- {description}"))?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use glob::glob;
+use modlet::modlet::ModletXML;
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// What formatting did (or would do) to a modlet XML file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// The file was not in the canonical format (and has been rewritten, unless checking)
+    Changed,
+    /// The file could not be formatted, e.g. it is invalid
+    Failed(String),
+    Unchanged,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub path: PathBuf,
+    pub status: Status,
+}
+
+/// Returns the file in the canonical format, if it isn't already
+fn formatted(path: &Path) -> eyre::Result<Option<String>> {
+    let source = fs::read_to_string(path)?;
+    let xml = ModletXML::new(path).load()?;
+
+    let formatted = xml.format()?;
+    Ok((formatted != source).then_some(formatted))
+}
+
+fn format_file(path: &Path, check: bool) -> eyre::Result<Status> {
+    let Some(formatted) = formatted(path)? else {
+        return Ok(Status::Unchanged);
+    };
+    if !check {
+        fs::write(path, formatted)?;
+    }
+
+    Ok(Status::Changed)
+}
+
+/// Rewrites each XML file in the modlets' `Config` directories in the canonical format
+///
+/// # Arguments
+///
+/// * `modlets` - The modlet(s) to format
+/// * `check` - Only report the files which are not formatted, without rewriting them
+///
+/// # Errors
+///
+/// * If a modlet's Config directory cannot be read
+///
+pub fn run(modlets: &[PathBuf], check: bool) -> eyre::Result<Vec<Outcome>> {
+    let mut paths = Vec::new();
+    for modlet in modlets {
+        for path in glob(modlet.join("Config/**/*.xml").to_str().unwrap())? {
+            paths.push(path?);
+        }
+    }
+
+    Ok(paths
+        .into_par_iter()
+        .map(|path| {
+            let status = format_file(&path, check).unwrap_or_else(|err| Status::Failed(err.to_string()));
+            Outcome { path, status }
+        })
+        .collect())
+}
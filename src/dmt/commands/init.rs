@@ -13,8 +13,7 @@ struct ModletPaths {
 }
 
 impl ModletPaths {
-    fn new(name: &str) -> Self {
-        let root = Path::new(".").join(name);
+    fn new(root: &Path) -> Self {
         let config = root.join("Config/.keep");
         let modinfo = root.join("ModInfo.xml");
         let readme = root.join("README.md");
@@ -29,7 +28,7 @@ impl ModletPaths {
 
 pub fn run(name: impl ToString, requested_version: Option<&RequestedVersion>) -> Result<bool, ModinfoError> {
     let name = name.to_string();
-    let modlet_paths = ModletPaths::new(&name);
+    let modlet_paths = ModletPaths::new(&Path::new(".").join(&name));
     if modlet_paths.modinfo.exists()
         && !Confirm::with_theme(&ColorfulTheme::default())
             .with_prompt(format!("Modlet {} already exists. Overwrite?", name))
@@ -40,12 +39,14 @@ pub fn run(name: impl ToString, requested_version: Option<&RequestedVersion>) ->
         return Ok(false);
     }
 
-    create(name, requested_version)
+    create(Path::new(".").join(name), requested_version)
 }
 
-pub fn create(name: impl ToString, requested_version: Option<&RequestedVersion>) -> Result<bool, ModinfoError> {
-    let name = name.to_string();
-    let modlet_paths = ModletPaths::new(&name);
+/// Creates a modlet at `path`, named after its directory
+pub fn create(path: impl AsRef<Path>, requested_version: Option<&RequestedVersion>) -> Result<bool, ModinfoError> {
+    let path = path.as_ref();
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let modlet_paths = ModletPaths::new(path);
     let modinfo_version = super::requested_version_to_modinfo_version(requested_version);
 
     fs::create_dir_all(modlet_paths.config)?;
@@ -0,0 +1,47 @@
+use modlet::lint::{Diagnostic, Linter, Rule, Severity};
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Clone)]
+pub struct LintOptions {
+    /// Rules which are not checked
+    pub allow: Vec<Rule>,
+    /// Rules reported as errors
+    pub deny: Vec<Rule>,
+    /// The game's install directory, used to check config file names
+    pub game_directory: Option<PathBuf>,
+    /// Rules reported as warnings
+    pub warn: Vec<Rule>,
+}
+
+/// Checks one or more modlets for common mistakes
+///
+/// # Arguments
+///
+/// * `modlets` - A list of modlet(s) to lint
+/// * `opts` - Lint options
+///
+/// # Errors
+///
+/// * If a modlet's Config directory cannot be read
+///
+pub fn run(modlets: &[PathBuf], opts: &LintOptions) -> eyre::Result<Vec<Diagnostic>> {
+    let mut linter = Linter::new();
+    for (rules, severity) in [
+        (&opts.allow, Severity::Allow),
+        (&opts.warn, Severity::Warning),
+        (&opts.deny, Severity::Error),
+    ] {
+        rules.iter().for_each(|&rule| linter.set_severity(rule, severity));
+    }
+    if let Some(game_directory) = &opts.game_directory {
+        linter.set_vanilla_config(Path::new(game_directory).join("Data/Config"));
+    }
+
+    let diagnostics = modlets
+        .par_iter()
+        .map(|modlet| linter.lint(modlet))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(diagnostics.into_iter().flatten().collect())
+}
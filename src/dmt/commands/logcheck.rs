@@ -0,0 +1,330 @@
+use modlet::modlet::{Command, Modlet, Span};
+use rayon::prelude::*;
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// An XML patching problem reported in the game's log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogIssue {
+    /// Further details, such as the exception logged after a failed patch
+    pub detail: Option<String>,
+    /// The config file being patched (e.g. `items.xml`)
+    pub file: String,
+    pub kind: IssueKind,
+    /// The line of the log the issue was reported on
+    pub line: usize,
+    /// The mod name, as the game reports it
+    pub mod_name: String,
+    /// The location of the command within the mod's config file, as the game reports it
+    pub position: Option<(usize, usize)>,
+    pub xpath: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IssueKind {
+    /// A command's xpath matched no nodes
+    DidNotApply,
+    /// An exception was thrown while applying the mod's config
+    Failed,
+}
+
+/// The modlet command an issue was caused by
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueSource {
+    pub command: Command,
+    /// The modlet the command came from. For bundles, the modlet named by the preceding `Included from` comment.
+    pub modlet: String,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for LogIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "log:{}: {} from {}", self.line, self.file, self.mod_name)?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{line}:{column}")?;
+        }
+        match self.kind {
+            IssueKind::DidNotApply => write!(f, ": did not apply")?,
+            IssueKind::Failed => write!(f, ": failed")?,
+        }
+        if let Some(xpath) = &self.xpath {
+            write!(f, " ({xpath})")?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, ": {detail}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for IssueSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let xpath = self
+            .command
+            .instructions()
+            .map(|is| String::from_utf8_lossy(&is.xpath).into_owned())
+            .unwrap_or_default();
+
+        write!(f, "{}", self.modlet)?;
+        if let Some(span) = &self.span {
+            write!(f, ": {span}")?;
+        }
+        write!(f, ": <{} xpath=\"{xpath}\">", self.command)
+    }
+}
+
+/// Returns the text between `prefix` and the next `terminator`
+fn between<'a>(text: &'a str, prefix: &str, terminator: char) -> Option<&'a str> {
+    let start = text.find(prefix)? + prefix.len();
+    let end = text[start..].find(terminator)?;
+
+    Some(&text[start..start + end])
+}
+
+/// Parses a `(line 12 at pos 5)` suffix
+fn position(text: &str) -> Option<(usize, usize)> {
+    let line = between(text, "(line ", ' ')?.parse().ok()?;
+    let column = between(text, " at pos ", ')')?.parse().ok()?;
+
+    Some((line, column))
+}
+
+/// Finds the XML patching warnings and errors in a game log, e.g.
+///
+/// ```text
+/// 2023-06-11T12:34:56 12.345 WRN XML patch for "items.xml" from mod "MyMod" did not apply: <set xpath="/items/item[@name='x']/@value" (line 5 at pos 6)
+/// 2023-06-11T12:34:56 12.345 ERR XML loader: Patching 'items.xml' from mod 'MyMod' failed:
+/// 2023-06-11T12:34:56 12.345 EXC Object reference not set to an instance of an object
+/// ```
+pub fn parse(log: &str) -> Vec<LogIssue> {
+    let mut issues: Vec<LogIssue> = Vec::new();
+
+    for (index, text) in log.lines().enumerate() {
+        let line = index + 1;
+
+        if let Some(rest) = text.split_once("XML patch for ").map(|(_, rest)| rest) {
+            if !rest.contains("did not apply") {
+                continue;
+            }
+            let (Some(file), Some(mod_name)) = (between(rest, "\"", '"'), between(rest, "from mod \"", '"')) else {
+                continue;
+            };
+
+            issues.push(LogIssue {
+                detail: None,
+                file: file.to_string(),
+                kind: IssueKind::DidNotApply,
+                line,
+                mod_name: mod_name.to_string(),
+                position: position(rest),
+                xpath: between(rest, "xpath=\"", '"').map(str::to_string),
+            });
+        } else if let Some(rest) = text.split_once("Patching '").map(|(_, rest)| rest) {
+            let (Some(file), Some(mod_name)) = (rest.split('\'').next(), between(rest, "from mod '", '\'')) else {
+                continue;
+            };
+
+            issues.push(LogIssue {
+                detail: None,
+                file: file.to_string(),
+                kind: IssueKind::Failed,
+                line,
+                mod_name: mod_name.to_string(),
+                position: None,
+                xpath: None,
+            });
+        } else if let Some((_, exception)) = text.split_once(" EXC ") {
+            // The exception explains the failure logged just before it
+            if let Some(issue) = issues.last_mut() {
+                if issue.kind == IssueKind::Failed && issue.detail.is_none() && issue.line + 1 == line {
+                    issue.detail = Some(exception.trim().to_string());
+                    issue.position = position(exception).or_else(|| {
+                        let line = between(exception, "Line ", ',')?.parse().ok()?;
+                        let column = between(exception, "position ", '.')?.parse().ok()?;
+                        Some((line, column))
+                    });
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+/// Whether a modlet is the one the game calls `mod_name`, by folder or ModInfo.xml name
+fn is_mod(modlet: &Modlet, mod_name: &str) -> bool {
+    modlet.name() == mod_name || modlet.modinfo.get_value_for("name").map(String::as_str) == Some(mod_name)
+}
+
+/// Finds the command an issue refers to: the one on the reported line or, failing that, the one with the
+/// reported xpath
+fn find_source(issue: &LogIssue, modlets: &[Modlet]) -> Option<IssueSource> {
+    let modlet = modlets.iter().find(|modlet| is_mod(modlet, &issue.mod_name))?;
+    let xml = modlet
+        .xmls
+        .iter()
+        .find(|xml| xml.filename().to_string_lossy() == issue.file)?;
+
+    // Flatten conditionals, remembering which modlet of a bundle each command was included from
+    let mut commands: Vec<(String, &Command)> = Vec::new();
+    let mut pending: Vec<&Command> = xml.commands.iter().rev().collect();
+    let mut source = modlet.name().to_string();
+    while let Some(command) = pending.pop() {
+        if let Some(name) = command.included_from() {
+            source = name.to_string();
+        }
+        if let Command::Conditional(branches) = command {
+            pending.extend(branches.iter().rev().flat_map(|branch| branch.commands.iter().rev()));
+        }
+        commands.push((source.clone(), command));
+    }
+
+    let xpath_of = |command: &Command| {
+        command
+            .instructions()
+            .map(|is| String::from_utf8_lossy(&is.xpath).into_owned())
+    };
+    let (source, command) = commands
+        .iter()
+        .find(|(_, command)| {
+            matches!((issue.position, command.span()), (Some((line, _)), Some(span)) if span.line == line)
+                && command.instructions().is_some()
+        })
+        .or_else(|| {
+            let xpath = issue.xpath.as_ref()?;
+            commands
+                .iter()
+                .find(|(_, command)| xpath_of(command).as_ref() == Some(xpath))
+        })?;
+
+    Some(IssueSource {
+        command: (*command).clone(),
+        modlet: source.clone(),
+        span: command.span().cloned(),
+    })
+}
+
+/// Parses the XML patching problems in a game log, mapping each back to the modlet command which caused it
+///
+/// # Arguments
+///
+/// * `log` - The game's player or server log (e.g. `output_log.txt`)
+/// * `modlets` - The modlet(s) (or packaged bundles) the game loaded
+///
+/// # Errors
+///
+/// * If the log cannot be read
+/// * If a modlet cannot be loaded
+///
+pub fn run(log: &Path, modlets: &[PathBuf]) -> eyre::Result<Vec<(LogIssue, Option<IssueSource>)>> {
+    let log = String::from_utf8_lossy(&fs::read(log)?).into_owned();
+    let modlets = modlets
+        .par_iter()
+        .map(Modlet::new)
+        .collect::<Result<Vec<Modlet>, _>>()?;
+
+    Ok(parse(&log)
+        .into_iter()
+        .map(|issue| {
+            let source = find_source(&issue, &modlets);
+            (issue, source)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmt::test_helpers::TempDir;
+    use modlet::modlet::ModletXML;
+    use rstest::rstest;
+
+    const DID_NOT_APPLY: &str = "2023-06-11T12:34:56 12.345 WRN XML patch for \"items.xml\" from mod \"MyMod\" did not apply: <set xpath=\"/items/item[@name='x']/@value\" (line 5 at pos 6)";
+    const FAILED: &str = "2023-06-11T12:34:56 12.345 ERR XML loader: Patching 'items.xml' from mod 'MyMod' failed:";
+
+    fn issue(kind: IssueKind, line: usize) -> LogIssue {
+        LogIssue {
+            detail: None,
+            file: "items.xml".to_string(),
+            kind,
+            line,
+            mod_name: "MyMod".to_string(),
+            position: None,
+            xpath: None,
+        }
+    }
+
+    #[rstest]
+    #[case::did_not_apply(
+        format!("INF Loading\n{DID_NOT_APPLY}"),
+        LogIssue {
+            position: Some((5, 6)),
+            xpath: Some("/items/item[@name='x']/@value".to_string()),
+            ..issue(IssueKind::DidNotApply, 2)
+        }
+    )]
+    #[case::failed(
+        format!("{FAILED}\n2023-06-11T12:34:56 12.345 EXC Object reference not set to an instance of an object"),
+        LogIssue {
+            detail: Some("Object reference not set to an instance of an object".to_string()),
+            ..issue(IssueKind::Failed, 1)
+        }
+    )]
+    #[case::failed_at_position(
+        format!("{FAILED}\n2023-06-11T12:34:56 12.345 EXC XmlException: Unexpected end tag. Line 12, position 3."),
+        LogIssue {
+            detail: Some("XmlException: Unexpected end tag. Line 12, position 3.".to_string()),
+            position: Some((12, 3)),
+            ..issue(IssueKind::Failed, 1)
+        }
+    )]
+    #[case::unrelated_exception(
+        format!("{FAILED}\nINF Loading\n2023-06-11T12:34:56 12.345 EXC Object reference not set to an instance of an object"),
+        issue(IssueKind::Failed, 1)
+    )]
+    fn test_parse(#[case] log: String, #[case] expected: LogIssue) {
+        assert_eq!(vec![expected], parse(&log));
+    }
+
+    #[rstest]
+    #[case::by_line(Some((6, 5)), None, ("ModB", "/e"))]
+    #[case::by_xpath(None, Some("/a/@b"), ("ModA", "/a/@b"))]
+    #[case::unmatched(Some((1, 1)), Some("/x"), ("", ""))]
+    fn test_find_source(
+        #[case] position: Option<(usize, usize)>,
+        #[case] xpath: Option<&str>,
+        #[case] expected: (&str, &str),
+    ) {
+        let dir = TempDir::new();
+        let path = dir.join("Bundle/Config/items.xml");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            "<config>\n    <!-- Included from ModA -->\n    <set xpath=\"/a/@b\">1</set>\n    <!-- Included from ModB -->\n    <set xpath=\"/c/@d\">2</set>\n    <remove xpath=\"/e\"/>\n</config>\n",
+        )
+        .unwrap();
+        let mut bundle = Modlet::empty(dir.join("Bundle"));
+        bundle.xmls.push(ModletXML::new(&path).load().unwrap());
+
+        let issue = LogIssue {
+            mod_name: "Bundle".to_string(),
+            position,
+            xpath: xpath.map(str::to_string),
+            ..issue(IssueKind::DidNotApply, 1)
+        };
+        let source = find_source(&issue, &[bundle]).map(|source| {
+            let xpath = String::from_utf8_lossy(&source.command.instructions().unwrap().xpath).into_owned();
+            (source.modlet, xpath)
+        });
+
+        assert_eq!(
+            expected,
+            source
+                .as_ref()
+                .map_or(("", ""), |(modlet, xpath)| (modlet.as_str(), xpath.as_str()))
+        );
+    }
+}
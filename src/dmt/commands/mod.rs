@@ -1,9 +1,18 @@
 use crate::cli::RequestedVersion;
 
+pub mod archive;
 pub mod bump;
 pub mod convert;
+pub mod diff_to_modlet;
+pub mod dump;
+pub mod explain;
+pub mod fmt;
 pub mod init;
+pub mod lint;
+pub mod logcheck;
 pub mod package;
+pub mod render;
+pub mod validate;
 
 pub fn requested_version_to_modinfo_version(requested_version: Option<&RequestedVersion>) -> modinfo::ModinfoVersion {
     match requested_version {
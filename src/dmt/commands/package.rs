@@ -2,7 +2,10 @@ use crate::dmt::{commands, SETTINGS};
 use color_eyre::eyre::eyre;
 use console::{style, Term};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use modlet::modlet::Modlet;
+use modlet::{
+    modlet::{AssetRules, FileConflicts, Modlet},
+    simulator::Simulator,
+};
 use quick_xml::{
     events::{BytesEnd, BytesStart, BytesText, Event},
     Writer,
@@ -14,8 +17,28 @@ use std::{
     path::{Path, PathBuf},
 };
 
-/// Reads a modlet's xml files
-fn load(path: impl AsRef<Path>, padding: usize, pb: &ProgressBar) -> eyre::Result<Modlet> {
+mod conflicts;
+mod manifest;
+pub mod order;
+pub use conflicts::Conflict;
+pub use manifest::{Manifest, DEFAULT_MANIFEST};
+
+#[derive(Debug, Default, Clone)]
+pub struct PackageOptions {
+    /// Fail instead of packaging when modlets modify the same xpath
+    pub deny_conflicts: bool,
+    /// Which of each modlet's files are packaged
+    pub files: AssetRules,
+    /// The game's install directory. When set, modlets are ordered after the modlets whose nodes they patch.
+    pub game_directory: Option<PathBuf>,
+    /// ModInfo.xml values to set in the output modlet
+    pub modinfo: BTreeMap<String, String>,
+    /// A file listing modlets in the order they should be loaded
+    pub order: Option<PathBuf>,
+}
+
+/// Reads a modlet's xml files, and finds the other files it packages
+fn load(path: impl AsRef<Path>, rules: &AssetRules, padding: usize, pb: &ProgressBar) -> eyre::Result<Modlet> {
     let path = path.as_ref().canonicalize().unwrap_or_default();
     let file_name = path.file_name().unwrap_or_default().to_str().unwrap();
     let verbose = SETTINGS.read().unwrap().verbosity > 0;
@@ -31,7 +54,9 @@ fn load(path: impl AsRef<Path>, padding: usize, pb: &ProgressBar) -> eyre::Resul
         ));
     }
 
-    let modlet = Modlet::new(path)?;
+    // A modlet's load order constraints are resolved when packaging, so don't apply to the package itself
+    let rules = rules.clone().exclude(order::LOAD_ORDER_FILE)?;
+    let modlet = Modlet::discover(path, &rules)?;
 
     Ok(modlet)
 }
@@ -100,13 +125,16 @@ fn file_map(modlets: &[Modlet]) -> BTreeMap<PathBuf, Vec<&Modlet>> {
 ///
 /// * `modlets` - A list of modlet(s) to package
 /// * `modlet` - The path to the modlet to package into
+/// * `opts` - Packaging options
 ///
 /// # Errors
 ///
 /// * If the game directory is invalid
 /// * If the modlet path is invalid
+/// * If modlets conflict and `opts.deny_conflicts` is set
+/// * If a declared dependency is missing, or the load order constraints form a cycle
 ///
-pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
+pub fn run(modlets: &[PathBuf], output_modlet: &Path, opts: &PackageOptions) -> eyre::Result<()> {
     let verbose = SETTINGS.read().unwrap().verbosity > 0;
     let modlet_count = modlets.len() as u64;
     let mp = MultiProgress::new();
@@ -140,13 +168,14 @@ pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
     }
 
     // Using `par_iter()` to parallelize the packaging of each modlet.
-    let mut loaded_modlets: Vec<Modlet> = modlets
+    // Modlets which fail to load are kept with their error, which is reported whatever the verbosity.
+    let (mut loaded_modlets, failures): (Vec<Modlet>, Vec<String>) = modlets
         .par_iter()
-        .fold(Vec::<Modlet>::new, |mut vf, path| {
+        .fold(<(Vec<Modlet>, Vec<String>)>::default, |(mut vf, mut failures), path| {
             let pb = mp.add(ProgressBar::new(modlet_count));
             pb.set_style(spinner_style.clone());
 
-            match load(path, padding, &pb) {
+            match load(path, &opts.files, padding, &pb) {
                 Ok(modlet) => {
                     if verbose {
                         pb.finish_with_message(style("OKAY").green().bold().to_string());
@@ -162,40 +191,80 @@ pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
                             style(format!("({err})")).red()
                         ));
                     }
+                    failures.push(format!("{}: {err}", path.display()));
                 }
             }
 
-            vf
+            (vf, failures)
         })
-        .reduce(Vec::<Modlet>::new, |mut vf, mut v| {
+        .reduce(Default::default, |(mut vf, mut failures), (mut v, mut f)| {
             vf.append(&mut v);
-            vf
+            failures.append(&mut f);
+            (vf, failures)
         });
 
     if (loaded_modlets.len() as u64) == modlet_count {
-        // Create the output modlet if necessary
-        if !output_modlet.exists() {
-            commands::init::create(output_modlet_name, None)?;
-        }
-
-        if config_dir.exists() {
-            if config_dir.is_dir() {
-                fs::remove_dir_all(&config_dir)?;
-            } else {
-                return Err(eyre!(
-                    "Invalid Modlet {}: Config directory is not a directory",
-                    config_dir.display()
-                ));
+        let mut vanilla = opts.game_directory.as_ref().map(Simulator::new).transpose()?;
+        let (loaded_modlets, dependencies) = order::resolve(loaded_modlets, opts.order.as_deref(), vanilla.as_mut())?;
+
+        if !dependencies.is_empty() {
+            term.write_line(
+                style(format!("\n{} load order dependency(ies) found:", dependencies.len()))
+                    .cyan()
+                    .bold()
+                    .to_string()
+                    .as_ref(),
+            )?;
+            for dependency in &dependencies {
+                term.write_line(style(format!("  {dependency}")).cyan().to_string().as_ref())?;
             }
         }
 
-        // Sort modlets by name to ensure consistent packaging
-        loaded_modlets.sort_by(|a, b| a.name().cmp(&b.name()));
-
         let modlets = loaded_modlets.clone();
         let files = file_map(&modlets);
         let files_count = files.len() as u64;
 
+        let conflicts: Vec<Conflict> = files
+            .iter()
+            .flat_map(|(file, modlets)| conflicts::detect(file, modlets))
+            .collect();
+
+        if !conflicts.is_empty() {
+            term.write_line(
+                style(format!("\n{} conflict(s) found:", conflicts.len()))
+                    .yellow()
+                    .bold()
+                    .to_string()
+                    .as_ref(),
+            )?;
+            for conflict in &conflicts {
+                term.write_line(style(format!("  {conflict}")).yellow().to_string().as_ref())?;
+                for span in &conflict.spans {
+                    term.write_line(style(format!("    at {span}")).dim().to_string().as_ref())?;
+                }
+            }
+
+            if opts.deny_conflicts {
+                return Err(eyre!("{} conflict(s) found, refusing to package", conflicts.len()));
+            }
+        }
+
+        // Create the output modlet if necessary
+        if !output_modlet.exists() {
+            commands::init::create(output_modlet, None)?;
+        }
+        if !opts.modinfo.is_empty() {
+            let modinfo_path = output_modlet.join("ModInfo.xml");
+            let mut modinfo = modinfo::parse(&modinfo_path)?;
+            for (key, value) in &opts.modinfo {
+                match key.as_str() {
+                    "version" => modinfo.set_version(value.clone()),
+                    key => modinfo.set_value_for(key, value),
+                }
+            }
+            modinfo.write(Some(&modinfo_path))?;
+        }
+
         if config_dir.exists() {
             if config_dir.is_dir() {
                 fs::remove_dir_all(&config_dir)?;
@@ -219,6 +288,7 @@ pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
                         if verbose {
                             pb.finish_with_message(style("OKAY").green().bold().to_string());
                         }
+                        Ok(())
                     }
                     Err(err) => {
                         if verbose {
@@ -228,10 +298,9 @@ pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
                                 style(format!("({err})")).red()
                             ));
                         }
+                        Err(err.wrap_err(format!("Failed to package {}", file.display())))
                     }
                 }
-
-                Ok(())
             })?;
 
         // Write other files
@@ -243,15 +312,59 @@ pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
             pb.set_prefix(format!("Packaging {:.<padding$}", "additional files"));
         }
 
+        // Replace copies left by a previous package, so that changed assets are picked up
+        for file in loaded_modlets.iter().flat_map(|modlet| {
+            modlet
+                .files
+                .iter()
+                .flatten()
+                .filter_map(|file| file.strip_prefix(&modlet.path).ok())
+        }) {
+            let destination = output_modlet.join(file);
+            if destination.is_file() {
+                fs::remove_file(destination)?;
+            }
+        }
+        let mut file_conflicts = FileConflicts::default();
         for modlet in loaded_modlets {
             if verbose {
                 pb.inc(1);
             }
 
-            modlet.write_files(output_modlet)?;
+            file_conflicts.extend(modlet.write_files(output_modlet)?);
         }
         pb.finish_with_message(style("OKAY").green().bold().to_string());
 
+        if !file_conflicts.assets.is_empty() {
+            term.write_line(
+                style(format!("\n{} file conflict(s) found:", file_conflicts.assets.len()))
+                    .yellow()
+                    .bold()
+                    .to_string()
+                    .as_ref(),
+            )?;
+            for conflict in &file_conflicts.assets {
+                term.write_line(style(format!("  {conflict}")).yellow().to_string().as_ref())?;
+            }
+        }
+
+        let localization_conflicts = &file_conflicts.localization;
+        if !localization_conflicts.is_empty() {
+            term.write_line(
+                style(format!(
+                    "\n{} localization conflict(s) found:",
+                    localization_conflicts.len()
+                ))
+                .yellow()
+                .bold()
+                .to_string()
+                .as_ref(),
+            )?;
+            for conflict in localization_conflicts {
+                term.write_line(style(format!("  {conflict}")).yellow().to_string().as_ref())?;
+            }
+        }
+
         term.write_line(
             style(format!(
                 "\n\n{modlet_count} modlet(s) successfully packaged into {}\n",
@@ -262,9 +375,13 @@ pub fn run(modlets: &[PathBuf], output_modlet: &Path) -> eyre::Result<()> {
             .as_ref(),
         )?;
     } else {
+        term.write_line("")?;
+        for failure in &failures {
+            term.write_line(style(failure).red().to_string().as_ref())?;
+        }
         term.write_line(
             style(format!(
-                "\n\n{count} modlet(s) failed to package!\n",
+                "\n{count} modlet(s) failed to package!\n",
                 count = modlet_count - (loaded_modlets.len() as u64)
             ))
             .red()
@@ -0,0 +1,202 @@
+use modlet::{
+    modlet::{Command, Modlet, Span},
+    xpath,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// An xpath (and attribute) modified by more than one modlet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub attribute: Option<String>,
+    pub file: PathBuf,
+    /// The conflicting modlets, in the order they are applied
+    pub modlets: Vec<String>,
+    /// Where each conflicting command was found
+    pub spans: Vec<Span>,
+    pub xpath: String,
+}
+
+impl Conflict {
+    /// The modlet whose change the game ends up with (the last one applied)
+    pub fn winner(&self) -> &str {
+        self.modlets.last().map(String::as_str).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.xpath)?;
+        if let Some(attribute) = &self.attribute {
+            write!(f, " (name=\"{attribute}\")")?;
+        }
+        write!(
+            f,
+            " is modified by {} (winner: {})",
+            self.modlets.join(", "),
+            self.winner()
+        )
+    }
+}
+
+/// Normalizes an xpath so trivially different spellings of the same path (whitespace, quoting) compare equal.
+/// An xpath which cannot be parsed is compared as written.
+fn normalize_xpath(xpath: &[u8]) -> String {
+    let xpath = String::from_utf8_lossy(xpath);
+
+    match xpath::parse_union(&xpath) {
+        Ok(paths) => paths.iter().map(ToString::to_string).collect::<Vec<_>>().join("|"),
+        Err(_) => xpath.into_owned(),
+    }
+}
+
+/// Returns the (xpath, attribute) a command overwrites, if it's one that can conflict
+fn target(command: &Command) -> Option<(String, Option<String>)> {
+    match command {
+        Command::Csv(is) | Command::Remove(is) | Command::Set(is) => Some((normalize_xpath(&is.xpath), None)),
+        Command::SetAttribute(is) => Some((
+            normalize_xpath(&is.xpath),
+            is.attribute
+                .as_ref()
+                .map(|attribute| String::from_utf8_lossy(attribute).into_owned()),
+        )),
+        _ => None,
+    }
+}
+
+/// Returns the commands, including those within every branch of a `<conditional>` block (as any of them may be
+/// the one the game applies)
+fn flatten(commands: &[Command]) -> Vec<&Command> {
+    commands
+        .iter()
+        .flat_map(|command| match command {
+            Command::Conditional(branches) => branches.iter().flat_map(|branch| flatten(&branch.commands)).collect(),
+            command => vec![command],
+        })
+        .collect()
+}
+
+/// Detects xpaths that are modified by more than one modlet within the same file
+///
+/// # Arguments
+///
+/// * `file` - The config file being packaged
+/// * `modlets` - The modlets which include `file`, in the order they will be packaged
+///
+pub fn detect(file: &Path, modlets: &[&Modlet]) -> Vec<Conflict> {
+    let mut targets = BTreeMap::<(String, Option<String>), (Vec<String>, Vec<Span>)>::new();
+
+    for modlet in modlets {
+        let name = modlet.name().to_string();
+        let commands = modlet
+            .xmls
+            .iter()
+            .filter(|xml| *xml.filename() == *file)
+            .flat_map(|xml| flatten(&xml.commands));
+
+        for command in commands {
+            let Some(target) = target(command) else {
+                continue;
+            };
+            let (names, spans) = targets.entry(target).or_default();
+            if !names.contains(&name) {
+                names.push(name.clone());
+            }
+            spans.extend(command.span().cloned());
+        }
+    }
+
+    targets
+        .into_iter()
+        .filter(|(_, (modlets, _))| modlets.len() > 1)
+        .map(|((xpath, attribute), (modlets, spans))| Conflict {
+            attribute,
+            file: file.to_path_buf(),
+            modlets,
+            spans,
+            xpath,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmt::test_helpers::modlet;
+    use modlet::modlet::{BranchKind, Condition, ConditionalBranch, InstructionSetBuilder};
+    use rstest::rstest;
+
+    fn set(xpath: &str) -> Command {
+        Command::Set(InstructionSetBuilder::new(xpath).text("1").build().unwrap())
+    }
+
+    fn set_attribute(xpath: &str, name: &str) -> Command {
+        Command::SetAttribute(
+            InstructionSetBuilder::new(xpath)
+                .attribute(name)
+                .text("1")
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[rstest]
+    #[case::same_xpath(set("/items/item[@name='a']/@value"), set("/items/item[@name='a']/@value"), 1)]
+    #[case::quoting(set("/items/item[@name='a']/@value"), set("/items/item[@name=\"a\"]/@value"), 1)]
+    #[case::whitespace(set("/items/item[@name='a']/@value"), set("/items/item[ @name = 'a' ]/@value"), 1)]
+    #[case::space_in_literal(set("/items/item[@name='a b']/@value"), set("/items/item[@name='ab']/@value"), 0)]
+    #[case::different_xpath(set("/items/item[@name='a']/@value"), set("/items/item[@name='b']/@value"), 0)]
+    #[case::same_attribute(set_attribute("/items/item", "x"), set_attribute("/items/item", "x"), 1)]
+    #[case::different_attribute(set_attribute("/items/item", "x"), set_attribute("/items/item", "y"), 0)]
+    fn test_detect(#[case] first: Command, #[case] second: Command, #[case] expected: usize) {
+        let a = modlet("A", vec![first]);
+        let b = modlet("B", vec![second]);
+
+        assert_eq!(expected, detect(Path::new("items.xml"), &[&a, &b]).len());
+    }
+
+    #[rstest]
+    #[case::packaged_order(vec!["A", "B"], "B")]
+    #[case::reversed(vec!["B", "A"], "A")]
+    fn test_winner(#[case] order: Vec<&str>, #[case] expected: &str) {
+        let modlets: Vec<Modlet> = order
+            .iter()
+            .map(|name| modlet(name, vec![set("/items/item[@name='a']/@value")]))
+            .collect();
+        let conflicts = detect(Path::new("items.xml"), &modlets.iter().collect::<Vec<_>>());
+
+        assert_eq!(order, conflicts[0].modlets);
+        assert_eq!(expected, conflicts[0].winner());
+    }
+
+    #[test]
+    fn test_conditional() {
+        let branch = ConditionalBranch {
+            commands: vec![set("/items/item[@name='a']/property[@name='x']/@value")],
+            condition: Some(Condition::parse("mod_loaded('B')").unwrap()),
+            kind: BranchKind::If,
+            span: None,
+        };
+        let a = modlet("A", vec![Command::Conditional(vec![branch])]);
+        let b = modlet("B", vec![set("/items/item[@name='a']/property[@name='x']/@value")]);
+
+        assert_eq!(1, detect(Path::new("items.xml"), &[&a, &b]).len());
+    }
+
+    #[test]
+    fn test_same_modlet() {
+        let a = modlet(
+            "A",
+            vec![
+                set("/items/item[@name='a']/@value"),
+                set("/items/item[@name=\"a\"]/@value"),
+            ],
+        );
+        let b = modlet("B", vec![set("/items/item[@name='b']/@value")]);
+
+        assert!(detect(Path::new("items.xml"), &[&a, &b]).is_empty());
+    }
+}
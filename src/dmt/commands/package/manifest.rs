@@ -0,0 +1,216 @@
+use color_eyre::eyre::eyre;
+use glob::glob;
+use modlet::modlet::AssetRules;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The manifest read when no other is given
+pub const DEFAULT_MANIFEST: &str = "bundles.yaml";
+
+/// Describes the bundles packaged from a repository of modlets:
+///
+/// ```yaml
+/// bundles:
+///   core:
+///     output: Dist/CoreBundle
+///     modlets: [Mods/Core*, Mods/SharedItems]
+///     modinfo:
+///       display_name: Core Bundle
+///       version: 1.2.0
+///     files:
+///       exclude: ["**/*.psd"]
+/// ```
+///
+/// Paths are relative to the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub bundles: BTreeMap<String, Bundle>,
+    /// The directory containing the manifest
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bundle {
+    #[serde(default)]
+    pub deny_conflicts: bool,
+    #[serde(default)]
+    pub files: AssetRules,
+    /// ModInfo.xml values to set in the output (e.g. `display_name`, `version`)
+    #[serde(default)]
+    pub modinfo: BTreeMap<String, String>,
+    /// Member modlet paths, which may be globs
+    pub modlets: Vec<String>,
+    /// An order file, as for `package --order`
+    pub order: Option<PathBuf>,
+    pub output: PathBuf,
+}
+
+impl Manifest {
+    /// # Errors
+    ///
+    /// * If the manifest cannot be read or parsed
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let source = fs::read_to_string(path).map_err(|err| eyre!("{}: {err}", path.display()))?;
+        let mut manifest: Self = serde_yaml::from_str(&source).map_err(|err| eyre!("{}: {err}", path.display()))?;
+        manifest.root = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+        Ok(manifest)
+    }
+
+    /// Finds the named bundles, or all of them if `names` is empty
+    ///
+    /// # Errors
+    ///
+    /// * If a bundle is not in the manifest
+    pub fn select(&self, names: &[String]) -> eyre::Result<Vec<(&String, &Bundle)>> {
+        if names.is_empty() {
+            return Ok(self.bundles.iter().collect());
+        }
+
+        names
+            .iter()
+            .map(|name| {
+                self.bundles.get_key_value(name).ok_or_else(|| {
+                    let known = self.bundles.keys().cloned().collect::<Vec<_>>().join(", ");
+                    eyre!("No bundle named {name} in the manifest (found: {known})")
+                })
+            })
+            .collect()
+    }
+
+    /// Resolves a path given in the manifest
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Expands a bundle's member modlet globs, in the order given
+    ///
+    /// # Errors
+    ///
+    /// * If a pattern is invalid, or matches no directories
+    pub fn modlets(&self, bundle: &Bundle) -> eyre::Result<Vec<PathBuf>> {
+        let mut modlets = Vec::new();
+
+        for pattern in &bundle.modlets {
+            let mut matched = glob(&self.path(pattern).to_string_lossy())?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_dir())
+                .peekable();
+            if matched.peek().is_none() {
+                return Err(eyre!("{pattern} matches no modlets"));
+            }
+
+            for path in matched {
+                if !modlets.contains(&path) {
+                    modlets.push(path);
+                }
+            }
+        }
+
+        Ok(modlets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmt::test_helpers::TempDir;
+    use rstest::rstest;
+
+    /// A manifest in `dir`, beside `Mods/CoreItems`, `Mods/CoreWeapons` and `Mods/Extras`
+    fn manifest(dir: &Path, source: &str) -> Manifest {
+        for name in ["CoreItems", "CoreWeapons", "Extras"] {
+            fs::create_dir_all(dir.join("Mods").join(name)).unwrap();
+        }
+        fs::write(dir.join("Mods").join("notes.txt"), "").unwrap();
+        let path = dir.join(DEFAULT_MANIFEST);
+        fs::write(&path, source).unwrap();
+
+        Manifest::load(&path).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new();
+        let manifest = manifest(
+            &dir,
+            "bundles:\n  core:\n    output: Dist/Core\n    modlets: [Mods/Core*]\n    modinfo:\n      version: 1.2.0\n",
+        );
+        let bundle = &manifest.bundles["core"];
+
+        assert_eq!(dir.as_ref(), manifest.root);
+        assert_eq!(dir.join("Dist/Core"), manifest.path(&bundle.output));
+        assert_eq!(Some(&"1.2.0".to_string()), bundle.modinfo.get("version"));
+        assert!(!bundle.deny_conflicts);
+        assert!(bundle.order.is_none());
+    }
+
+    #[test]
+    fn test_load_unknown_field() {
+        let dir = TempDir::new();
+        let path = dir.join(DEFAULT_MANIFEST);
+        fs::write(&path, "bundles: {}\nextra: true\n").unwrap();
+        let err = Manifest::load(&path).unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}: unknown field `extra`", path.display())));
+    }
+
+    #[rstest]
+    #[case::glob(vec!["Mods/Core*"], vec!["CoreItems", "CoreWeapons"])]
+    #[case::in_order_given(vec!["Mods/Extras", "Mods/CoreItems"], vec!["Extras", "CoreItems"])]
+    #[case::deduplicated(vec!["Mods/CoreWeapons", "Mods/*", "Mods/Extras"], vec!["CoreWeapons", "CoreItems", "Extras"])]
+    fn test_modlets(#[case] patterns: Vec<&str>, #[case] expected: Vec<&str>) {
+        let dir = TempDir::new();
+        let manifest = manifest(
+            &dir,
+            &format!(
+                "bundles:\n  core:\n    output: Dist\n    modlets: [{}]\n",
+                patterns.join(", ")
+            ),
+        );
+        let modlets = manifest.modlets(&manifest.bundles["core"]).unwrap();
+        let expected: Vec<PathBuf> = expected.iter().map(|name| dir.join("Mods").join(name)).collect();
+
+        assert_eq!(expected, modlets);
+    }
+
+    #[test]
+    fn test_modlets_unmatched() {
+        let dir = TempDir::new();
+        let manifest = manifest(
+            &dir,
+            "bundles:\n  core:\n    output: Dist\n    modlets: [Mods/Missing*]\n",
+        );
+        let err = manifest.modlets(&manifest.bundles["core"]).unwrap_err();
+
+        assert_eq!("Mods/Missing* matches no modlets", err.to_string());
+    }
+
+    #[rstest]
+    #[case::all(vec![], Ok(vec!["core", "extras"]))]
+    #[case::named(vec!["extras"], Ok(vec!["extras"]))]
+    #[case::unknown(vec!["core", "missing"], Err("No bundle named missing in the manifest (found: core, extras)"))]
+    fn test_select(#[case] names: Vec<&str>, #[case] expected: Result<Vec<&str>, &str>) {
+        let dir = TempDir::new();
+        let manifest = manifest(
+            &dir,
+            "bundles:\n  extras:\n    output: Dist/Extras\n    modlets: [Mods/Extras]\n  core:\n    output: Dist/Core\n    modlets: [Mods/Core*]\n",
+        );
+        let names: Vec<String> = names.into_iter().map(String::from).collect();
+        let selected = manifest
+            .select(&names)
+            .map(|bundles| bundles.into_iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>())
+            .map_err(|err| err.to_string());
+
+        assert_eq!(expected.map_err(String::from), selected);
+    }
+}
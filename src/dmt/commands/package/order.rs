@@ -0,0 +1,490 @@
+use color_eyre::eyre::eyre;
+use modlet::{
+    modlet::{Command, Modlet},
+    simulator::Simulator,
+};
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The file, beside a modlet's ModInfo.xml, declaring its load order constraints
+pub const LOAD_ORDER_FILE: &str = "LoadOrder.yaml";
+
+/// The load order constraints a modlet declares in its `LoadOrder.yaml`:
+///
+/// ```yaml
+/// dependencies: [CoreItems]  # must be packaged too, and are loaded first
+/// load_after: [OtherMod]     # loaded first, if packaged
+/// load_before: [ThirdMod]    # loaded after this modlet, if packaged
+/// ```
+///
+/// Modlets are named by folder or by their ModInfo.xml name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LoadOrder {
+    dependencies: Vec<String>,
+    load_after: Vec<String>,
+    load_before: Vec<String>,
+}
+
+/// A modlet which must be loaded after another
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    /// The modlet which must be loaded first
+    pub dependency: String,
+    pub modlet: String,
+    pub reason: Reason,
+}
+
+/// Why one modlet must be loaded after another
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// Listed in the modlet's `dependencies`
+    Declared,
+    /// Listed in the modlet's `load_after`
+    LoadAfter,
+    /// Listed in the dependency's `load_before`
+    LoadBefore,
+    /// Listed after the dependency in an explicit order file
+    OrderFile,
+    /// A command only matches nodes added by the dependency
+    Patches { command: Command, file: PathBuf },
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} loads after {} (", self.modlet, self.dependency)?;
+        match &self.reason {
+            Reason::Declared => write!(f, "declared dependency")?,
+            Reason::LoadAfter => write!(f, "load_after")?,
+            Reason::LoadBefore => write!(f, "load_before of {}", self.dependency)?,
+            Reason::OrderFile => write!(f, "order file")?,
+            Reason::Patches { command, file } => {
+                let xpath = command
+                    .instructions()
+                    .map(|is| String::from_utf8_lossy(&is.xpath).into_owned())
+                    .unwrap_or_default();
+                write!(f, "patches {}: {xpath}", file.display())?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+/// Finds the folder name of the modlet called `name`, by folder or ModInfo.xml name
+fn find<'a>(modlets: &'a [Modlet], name: &str) -> Option<Cow<'a, str>> {
+    modlets
+        .iter()
+        .find(|modlet| {
+            modlet.name().eq_ignore_ascii_case(name)
+                || modlet
+                    .modinfo
+                    .get_value_for("name")
+                    .is_some_and(|modinfo_name| modinfo_name.eq_ignore_ascii_case(name))
+        })
+        .map(Modlet::name)
+}
+
+/// Reads the load order constraints each modlet declares in its `LoadOrder.yaml`
+///
+/// # Errors
+///
+/// * If a `LoadOrder.yaml` cannot be read or parsed
+/// * If a declared dependency is not among the modlets being packaged
+///
+/// A modlet naming itself is ignored.
+pub fn declared(modlets: &[Modlet]) -> eyre::Result<Vec<Dependency>> {
+    let mut dependencies = Vec::new();
+    let mut missing = Vec::new();
+
+    for modlet in modlets {
+        let path = modlet.path.join(LOAD_ORDER_FILE);
+        if !path.exists() {
+            continue;
+        }
+        let load_order: LoadOrder =
+            serde_yaml::from_str(&fs::read_to_string(&path)?).map_err(|err| eyre!("{}: {err}", path.display()))?;
+        let name = modlet.name().to_string();
+
+        for dependency in &load_order.dependencies {
+            match find(modlets, dependency) {
+                Some(dependency) => dependencies.push(Dependency {
+                    dependency: dependency.to_string(),
+                    modlet: name.clone(),
+                    reason: Reason::Declared,
+                }),
+                None => missing.push(format!("{name} depends on {dependency}, which is not being packaged")),
+            }
+        }
+        for dependency in load_order.load_after.iter().filter_map(|other| find(modlets, other)) {
+            dependencies.push(Dependency {
+                dependency: dependency.to_string(),
+                modlet: name.clone(),
+                reason: Reason::LoadAfter,
+            });
+        }
+        for other in load_order.load_before.iter().filter_map(|other| find(modlets, other)) {
+            dependencies.push(Dependency {
+                dependency: name.clone(),
+                modlet: other.to_string(),
+                reason: Reason::LoadBefore,
+            });
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(eyre!("Missing dependencies:\n  {}", missing.join("\n  ")));
+    }
+    dependencies.retain(|d| d.dependency != d.modlet);
+
+    Ok(dependencies)
+}
+
+/// Reads an explicit load order: one modlet per line, with blank lines and `#` comments ignored.
+/// Each listed modlet is loaded after the one listed before it (unless both lines name the same modlet); unlisted
+/// modlets are only ordered by their own constraints.
+///
+/// # Errors
+///
+/// * If the file cannot be read
+/// * If it names a modlet which is not being packaged
+pub fn from_file(path: &Path, modlets: &[Modlet]) -> eyre::Result<Vec<Dependency>> {
+    let names = fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            find(modlets, line)
+                .map(|name| name.to_string())
+                .ok_or_else(|| eyre!("{}: {line} is not being packaged", path.display()))
+        })
+        .collect::<eyre::Result<Vec<String>>>()?;
+
+    Ok(names
+        .windows(2)
+        .filter(|pair| pair[0] != pair[1])
+        .map(|pair| Dependency {
+            dependency: pair[0].clone(),
+            modlet: pair[1].clone(),
+            reason: Reason::OrderFile,
+        })
+        .collect())
+}
+
+/// Finds commands which match nothing in the vanilla configs, but match once another modlet has been applied
+///
+/// # Errors
+///
+/// * If a vanilla config file cannot be loaded
+pub fn infer(modlets: &[Modlet], vanilla: &Simulator) -> eyre::Result<Vec<Dependency>> {
+    // The vanilla configs with each modlet applied on its own. Commands which cannot be applied (such as those with
+    // an invalid xpath, reported by `validate`) are skipped, so the rest of the modlet still counts.
+    let mut patched: Vec<Simulator> = modlets
+        .iter()
+        .map(|modlet| {
+            let mut simulator = vanilla.clone();
+            for xml in &modlet.xmls {
+                let file = xml.filename();
+                for command in simulator.active_commands(&xml.commands) {
+                    let _ = simulator.apply_command(&file, command);
+                }
+            }
+            simulator
+        })
+        .collect();
+    let mut vanilla = vanilla.clone();
+    let mut dependencies = Vec::new();
+
+    for modlet in modlets {
+        for xml in &modlet.xmls {
+            let file = xml.filename();
+            for command in vanilla.active_commands(&xml.commands) {
+                // Invalid xpaths are reported by `validate`, not here
+                if command.instructions().is_none() || vanilla.matches(&file, command).unwrap_or(1) > 0 {
+                    continue;
+                }
+
+                for (other, simulator) in modlets.iter().zip(patched.iter_mut()) {
+                    if other.name() != modlet.name() && simulator.matches(&file, command)? > 0 {
+                        dependencies.push(Dependency {
+                            dependency: other.name().to_string(),
+                            modlet: modlet.name().to_string(),
+                            reason: Reason::Patches {
+                                command: command.clone(),
+                                file: file.to_path_buf(),
+                            },
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Orders modlets the way the game loads its Mods folder (by folder name, case-insensitively), then moves each after
+/// the modlets it depends on: those declared in `LoadOrder.yaml`, listed in `order_file`, and (given the vanilla
+/// configs) inferred from its commands
+///
+/// `vanilla` is told which modlets are loaded, so its `mod_loaded()` conditionals see all of them.
+///
+/// # Errors
+///
+/// * If a `LoadOrder.yaml` or the order file cannot be read, or names a modlet which is not loaded
+/// * If a vanilla config file cannot be loaded
+/// * If the dependencies form a cycle
+///
+/// Returns the ordered modlets, and the dependencies found
+pub fn resolve(
+    mut modlets: Vec<Modlet>,
+    order_file: Option<&Path>,
+    vanilla: Option<&mut Simulator>,
+) -> eyre::Result<(Vec<Modlet>, Vec<Dependency>)> {
+    modlets.sort_by_key(|modlet| modlet.name().to_lowercase());
+    let mut dependencies = declared(&modlets)?;
+    if let Some(order_file) = order_file {
+        dependencies.extend(from_file(order_file, &modlets)?);
+    }
+    if let Some(vanilla) = vanilla {
+        vanilla.set_loaded_mods(modlets.iter().map(Modlet::name));
+        dependencies.extend(infer(&modlets, vanilla)?);
+    }
+
+    Ok((sort(modlets, &dependencies)?, dependencies))
+}
+
+/// Orders modlets so that each is loaded after its dependencies (a stable topological sort), keeping the existing
+/// order otherwise
+///
+/// # Errors
+///
+/// * If the dependencies form a cycle
+pub fn sort(modlets: Vec<Modlet>, dependencies: &[Dependency]) -> eyre::Result<Vec<Modlet>> {
+    let depends_on = |modlet: &Modlet, other: &Modlet| {
+        modlet.name() != other.name()
+            && dependencies
+                .iter()
+                .any(|d| d.modlet == modlet.name() && d.dependency == other.name())
+    };
+    let mut remaining = modlets;
+    let mut sorted = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let Some(index) = remaining
+            .iter()
+            .position(|modlet| !remaining.iter().any(|other| depends_on(modlet, other)))
+        else {
+            return Err(eyre!(
+                "Modlet dependencies form a cycle: {}",
+                cycle(&remaining, dependencies)
+            ));
+        };
+        sorted.push(remaining.remove(index));
+    }
+
+    Ok(sorted)
+}
+
+/// Describes a cycle among modlets which all have unsorted dependencies, e.g. `A -> B -> A`
+fn cycle(modlets: &[Modlet], dependencies: &[Dependency]) -> String {
+    let names: Vec<String> = modlets.iter().map(|modlet| modlet.name().to_string()).collect();
+    let mut path = vec![names[0].clone()];
+
+    loop {
+        let current = path.last().unwrap();
+        let Some(next) = dependencies
+            .iter()
+            .find(|d| d.modlet == *current && names.contains(&d.dependency))
+            .map(|d| d.dependency.clone())
+        else {
+            break;
+        };
+
+        let seen = path.iter().position(|name| *name == next);
+        path.push(next);
+        if let Some(start) = seen {
+            path.drain(..start);
+            break;
+        }
+    }
+
+    path.join(" -> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmt::test_helpers::{modlet, TempDir};
+    use modlet::modlet::InstructionSet;
+    use rstest::rstest;
+
+    /// `ModA` (named `Core Items` by its ModInfo.xml), `ModB` and `ModC`, with `ModB` declaring `load_order`
+    fn packaged(dir: &Path, load_order: &str) -> Vec<Modlet> {
+        ["ModA", "ModB", "ModC"]
+            .into_iter()
+            .map(|name| {
+                let mut modlet = Modlet::empty(dir.join(name));
+                fs::create_dir_all(&modlet.path).unwrap();
+                match name {
+                    "ModA" => modlet.modinfo.set_value_for("name", "Core Items"),
+                    "ModB" => fs::write(modlet.path.join(LOAD_ORDER_FILE), load_order).unwrap(),
+                    _ => (),
+                }
+                modlet
+            })
+            .collect()
+    }
+
+    fn pairs(dependencies: &[Dependency]) -> Vec<(&str, &str)> {
+        dependencies
+            .iter()
+            .map(|d| (d.dependency.as_str(), d.modlet.as_str()))
+            .collect()
+    }
+
+    /// Dependencies from `(dependency, modlet)` pairs
+    fn ordered(pairs: &[(&str, &str)]) -> Vec<Dependency> {
+        pairs
+            .iter()
+            .map(|(dependency, modlet)| Dependency {
+                dependency: dependency.to_string(),
+                modlet: modlet.to_string(),
+                reason: Reason::OrderFile,
+            })
+            .collect()
+    }
+
+    fn names(modlets: &[Modlet]) -> Vec<String> {
+        modlets.iter().map(|modlet| modlet.name().to_string()).collect()
+    }
+
+    #[rstest]
+    #[case::folder_name("dependencies: [moda]", vec![("ModA", "ModB")])]
+    #[case::modinfo_name("load_after: [Core Items]", vec![("ModA", "ModB")])]
+    #[case::modinfo_name_case_insensitive("load_after: [core items]", vec![("ModA", "ModB")])]
+    #[case::load_before("load_before: [ModC]", vec![("ModB", "ModC")])]
+    #[case::not_packaged("load_after: [Missing]\nload_before: [Missing]", vec![])]
+    #[case::self_edge("dependencies: [ModB]\nload_after: [modb]\nload_before: [ModB]", vec![])]
+    fn test_declared(#[case] load_order: &str, #[case] expected: Vec<(&str, &str)>) {
+        let dir = TempDir::new();
+        let dependencies = declared(&packaged(&dir, load_order)).unwrap();
+
+        assert_eq!(expected, pairs(&dependencies));
+    }
+
+    #[test]
+    fn test_missing_dependency() {
+        let dir = TempDir::new();
+        let err = declared(&packaged(&dir, "dependencies: [Missing, ModA]")).unwrap_err();
+
+        assert_eq!(
+            "Missing dependencies:\n  ModB depends on Missing, which is not being packaged",
+            err.to_string()
+        );
+    }
+
+    #[rstest]
+    #[case::comments_and_blank_lines("# Load order\n\nModC\n   \n# ModB\n  ModA  \n", vec![("ModC", "ModA")])]
+    #[case::modinfo_name("Core Items\nModB\nModC", vec![("ModA", "ModB"), ("ModB", "ModC")])]
+    #[case::self_edge("ModA\nCore Items\nModB", vec![("ModA", "ModB")])]
+    fn test_from_file(#[case] order: &str, #[case] expected: Vec<(&str, &str)>) {
+        let dir = TempDir::new();
+        let path = dir.join("order.txt");
+        fs::write(&path, order).unwrap();
+        let dependencies = from_file(&path, &packaged(&dir, "")).unwrap();
+
+        assert_eq!(expected, pairs(&dependencies));
+    }
+
+    #[test]
+    fn test_from_file_not_packaged() {
+        let dir = TempDir::new();
+        let path = dir.join("order.txt");
+        fs::write(&path, "ModA\nMissing\n").unwrap();
+        let err = from_file(&path, &packaged(&dir, "")).unwrap_err();
+
+        assert_eq!(
+            format!("{}: Missing is not being packaged", path.display()),
+            err.to_string()
+        );
+    }
+
+    #[rstest]
+    #[case::unconstrained(vec![], vec!["A", "B", "C"])]
+    #[case::stable(vec![("C", "A")], vec!["B", "C", "A"])]
+    #[case::chain(vec![("C", "B"), ("B", "A")], vec!["C", "B", "A"])]
+    #[case::unpackaged_dependency(vec![("Missing", "A")], vec!["A", "B", "C"])]
+    fn test_sort(#[case] dependencies: Vec<(&str, &str)>, #[case] expected: Vec<&str>) {
+        let modlets = ["A", "B", "C"].into_iter().map(Modlet::empty).collect();
+
+        assert_eq!(expected, names(&sort(modlets, &ordered(&dependencies)).unwrap()));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let modlets = ["A", "B", "C"].into_iter().map(Modlet::empty).collect();
+        let dependencies = ordered(&[("A", "B"), ("C", "B"), ("B", "C")]);
+
+        assert_eq!(
+            "Modlet dependencies form a cycle: B -> C -> B",
+            sort(modlets, &dependencies).unwrap_err().to_string()
+        );
+    }
+
+    #[rstest]
+    #[case::case_insensitive(None, vec!["alpha", "Beta", "ModB", "ModA", "ModC"])]
+    #[case::declared_and_order_file(Some("ModC\nalpha"), vec!["Beta", "ModB", "ModA", "ModC", "alpha"])]
+    fn test_resolve(#[case] order: Option<&str>, #[case] expected: Vec<&str>) {
+        let dir = TempDir::new();
+        let mut modlets = packaged(&dir, "load_before: [ModA]");
+        modlets.extend(["Beta", "alpha"].into_iter().map(|name| Modlet::empty(dir.join(name))));
+        let path = dir.join("order.txt");
+        if let Some(order) = order {
+            fs::write(&path, order).unwrap();
+        }
+        let (modlets, _) = resolve(modlets, order.map(|_| path.as_path()), None).unwrap();
+
+        assert_eq!(expected, names(&modlets));
+    }
+
+    fn command(xpath: &str, xml: &str) -> Command {
+        Command::Append(InstructionSet::builder(xpath).xml(xml).build().unwrap())
+    }
+
+    #[test]
+    fn test_infer_skips_invalid_commands() {
+        let game = TempDir::new();
+        fs::create_dir_all(game.join("Data/Config")).unwrap();
+        fs::write(
+            game.join("Data/Config/items.xml"),
+            "<items><item name=\"gunPistol\"/></items>",
+        )
+        .unwrap();
+        let vanilla = Simulator::new(&game).unwrap();
+
+        let invalid = Command::Remove(InstructionSet {
+            xpath: b"/items/item[@name=".to_vec(),
+            ..InstructionSet::new()
+        });
+        let modlets = [
+            modlet("A", vec![invalid, command("/items", "<item name=\"gunRifle\"/>")]),
+            modlet(
+                "B",
+                vec![command("/items/item[@name='gunRifle']", "<property name=\"Tags\"/>")],
+            ),
+        ];
+        let dependencies = infer(&modlets, &vanilla).unwrap();
+
+        assert_eq!(
+            vec![("A", "B")],
+            dependencies
+                .iter()
+                .map(|d| (d.dependency.as_str(), d.modlet.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use super::package::order;
+use modlet::{modlet::Modlet, simulator::Simulator};
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct RenderOptions {
+    /// Precede each changed node with a comment naming the modlet and command which changed it
+    pub annotate: bool,
+}
+
+/// Applies one or more modlets (or packaged bundles) to the vanilla game configs, and writes every resulting
+/// config file to `output`
+///
+/// # Arguments
+///
+/// * `modlets` - A list of modlet(s) to apply, in the order `package` would load them
+/// * `game_directory` - The game's install directory
+/// * `output` - The directory to write the rendered configs to
+/// * `opts` - Render options
+///
+/// # Errors
+///
+/// * If the game directory is invalid
+/// * If a modlet or vanilla config cannot be loaded
+/// * If the modlets' load order cannot be resolved (e.g. a dependency cycle)
+/// * If a command cannot be applied (e.g. an invalid xpath)
+/// * If the output cannot be written
+///
+/// Returns the paths of the files written
+pub fn run(
+    modlets: &[PathBuf],
+    game_directory: &Path,
+    output: &Path,
+    opts: &RenderOptions,
+) -> eyre::Result<Vec<PathBuf>> {
+    let mut simulator = Simulator::new(game_directory)?;
+    let modlets = modlets
+        .par_iter()
+        .map(Modlet::new)
+        .collect::<Result<Vec<Modlet>, _>>()?;
+
+    let (modlets, _) = order::resolve(modlets, None, Some(&mut simulator))?;
+    simulator.set_annotate(opts.annotate);
+    simulator.load_all()?;
+
+    for modlet in &modlets {
+        simulator.apply(modlet)?;
+    }
+
+    let mut written = Vec::new();
+    for (file, document) in simulator.documents() {
+        let path = output.join(file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, document.to_xml()? + "\n")?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
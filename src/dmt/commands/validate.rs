@@ -0,0 +1,119 @@
+use super::package::order;
+use modlet::{
+    modlet::{Command, Modlet},
+    simulator::Simulator,
+};
+use rayon::prelude::*;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A command which has no effect on the vanilla configs
+#[derive(Debug)]
+pub struct DeadPatch {
+    pub command: Command,
+    pub file: PathBuf,
+    pub modlet: String,
+    pub reason: Reason,
+}
+
+/// Why a command has no effect
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// The command could not be evaluated at all (e.g. an invalid xpath)
+    Error(String),
+    /// The command's xpath selects nothing
+    MatchesNothing,
+    /// The command's xpath matches, but the command doesn't change anything (e.g. a `set` to the current value).
+    /// Reported as a warning, as the patch is harmless but redundant.
+    NoEffect,
+}
+
+impl DeadPatch {
+    pub fn is_warning(&self) -> bool {
+        self.reason == Reason::NoEffect
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Error(err) => write!(f, "{err}"),
+            Reason::MatchesNothing => write!(f, "matches nothing"),
+            Reason::NoEffect => write!(f, "has no effect"),
+        }
+    }
+}
+
+impl fmt::Display for DeadPatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let xpath = self
+            .command
+            .instructions()
+            .map(|is| String::from_utf8_lossy(&is.xpath).into_owned())
+            .unwrap_or_default();
+
+        write!(f, "{}: {}", self.modlet, self.file.display())?;
+        if let Some(span) = self.command.span() {
+            write!(f, ":{}:{}", span.line, span.column)?;
+        }
+        write!(f, ": <{} xpath=\"{xpath}\"> {}", self.command, self.reason)
+    }
+}
+
+/// Applies a modlet's commands to a copy of the vanilla configs, collecting those which match nothing or change
+/// nothing
+fn validate(modlet: &Modlet, vanilla: &Simulator) -> Vec<DeadPatch> {
+    let mut simulator = vanilla.clone();
+    let mut dead_patches = Vec::new();
+
+    for xml in &modlet.xmls {
+        let file = xml.filename();
+        for command in vanilla.active_commands(&xml.commands) {
+            let reason = match simulator.apply_command(&file, command) {
+                Ok(Some(effect)) if effect.matched == 0 => Reason::MatchesNothing,
+                Ok(Some(effect)) if effect.is_noop() => Reason::NoEffect,
+                Ok(_) => continue,
+                Err(err) => Reason::Error(err.to_string()),
+            };
+
+            dead_patches.push(DeadPatch {
+                command: command.clone(),
+                file: file.to_path_buf(),
+                modlet: modlet.name().to_string(),
+                reason,
+            });
+        }
+    }
+
+    dead_patches
+}
+
+/// Validates one or more modlets against the vanilla game configs
+///
+/// # Arguments
+///
+/// * `modlets` - A list of modlet(s) to validate
+/// * `game_directory` - The game's install directory
+///
+/// # Errors
+///
+/// * If the game directory is invalid
+/// * If a modlet cannot be loaded
+/// * If the modlets' load order cannot be resolved (e.g. a dependency cycle)
+///
+pub fn run(modlets: &[PathBuf], game_directory: &Path) -> eyre::Result<Vec<DeadPatch>> {
+    let mut vanilla = Simulator::new(game_directory)?;
+    let modlets = modlets
+        .par_iter()
+        .map(Modlet::new)
+        .collect::<Result<Vec<Modlet>, _>>()?;
+
+    let (modlets, _) = order::resolve(modlets, None, Some(&mut vanilla))?;
+
+    Ok(modlets
+        .par_iter()
+        .flat_map(|modlet| validate(modlet, &vanilla))
+        .collect())
+}
@@ -0,0 +1,55 @@
+//! Fixtures shared by the command tests
+use modlet::modlet::{Command, Modlet};
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A new, empty directory for a test's files, removed (with everything in it) when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "7dmt-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        Self(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A modlet (which isn't on disk) patching `items.xml` with `commands`
+pub fn modlet(name: &str, commands: Vec<Command>) -> Modlet {
+    let mut modlet = Modlet::empty(name);
+    for command in commands {
+        modlet.add_command("items.xml", command);
+    }
+
+    modlet
+}
@@ -10,6 +10,7 @@ pub struct CommandResult {
     errors: Vec<cli::CliError>,
     messages: Vec<String>,
     verbose: u8,
+    warnings: Vec<String>,
 }
 
 fn main() -> Result<()> {
@@ -19,6 +20,10 @@ fn main() -> Result<()> {
     let stderr = Term::stderr();
     let result = cli::run()?;
 
+    for warning in &result.warnings {
+        stderr.write_line(format!("{}", style(warning).yellow()).as_ref())?;
+    }
+
     if result.errors.is_empty() {
         if result.verbose >= 1 {
             for message in result.messages {